@@ -1,5 +1,3 @@
-use rfc2047_decoder;
-
 fn main() {
     let encoded_str = "=?UTF-8?Q?str?=";
     let decoded_str = "str";
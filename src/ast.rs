@@ -0,0 +1,242 @@
+use std::{fmt, ops::Range};
+
+use crate::evaluator::Evaluated;
+use crate::parser::Node;
+
+/// Encoding of an encoded word, as defined in RFC 2047 section 4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// The Q encoding, similar to quoted-printable.
+    Q,
+    /// The B encoding, identical to base64.
+    B,
+}
+
+impl Encoding {
+    /// Parse the given encoding label, case-insensitively. The names of
+    /// the MIME transfer encodings Q and B derive from are accepted too,
+    /// and an empty label is taken as Q.
+    pub(crate) fn from_label(label: &[u8]) -> Option<Self> {
+        match label.to_ascii_uppercase().as_slice() {
+            b"" | b"Q" | b"QUOTED-PRINTABLE" => Some(Encoding::Q),
+            b"B" | b"BASE64" => Some(Encoding::B),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encoding::Q => write!(f, "Q"),
+            Encoding::B => write!(f, "B"),
+        }
+    }
+}
+
+/// Clear text of a header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClearText {
+    /// Decoded text.
+    pub text: String,
+    /// Byte span of the text in the header.
+    pub span: Range<usize>,
+}
+
+/// Encoded word of a header, like `=?utf-8*en?Q?caf=C3=A9?=`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodedWord {
+    /// Charset label, as written in the encoded word.
+    pub charset: String,
+    /// Canonical name of the charset the word was decoded with, like
+    /// `UTF-8` or `windows-1252`, or `None` if the label is unknown.
+    pub canonical_charset: Option<String>,
+    /// RFC 2231 language tag, if any.
+    pub language: Option<String>,
+    /// Encoding of the encoded text, or `None` if it is neither Q nor
+    /// B, in which case the word cannot be decoded.
+    pub encoding: Option<Encoding>,
+    /// Decoded text. A character split across consecutive encoded words
+    /// belongs to the word it ends in.
+    pub text: String,
+    /// Byte span of the whole encoded word in the header, delimiters
+    /// included.
+    pub span: Range<usize>,
+}
+
+/// Segment of a parsed header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    ClearText(ClearText),
+    EncodedWord(EncodedWord),
+}
+
+impl Segment {
+    pub(crate) fn new(node: Node, evaluated: Evaluated) -> Self {
+        let Evaluated { text, charset, .. } = evaluated;
+
+        match node {
+            Node::EncodedBytes(node) => Segment::EncodedWord(EncodedWord {
+                charset: String::from_utf8_lossy(&node.charset).to_string(),
+                canonical_charset: charset,
+                language: node
+                    .language
+                    .map(|language| String::from_utf8_lossy(&language).to_string()),
+                encoding: node.encoding,
+                text,
+                span: node.span,
+            }),
+            Node::ClearBytes(node) => Segment::ClearText(ClearText {
+                text,
+                span: node.span,
+            }),
+        }
+    }
+
+    /// Decoded text of the segment.
+    pub fn text(&self) -> &str {
+        match self {
+            Segment::ClearText(clear_text) => &clear_text.text,
+            Segment::EncodedWord(encoded_word) => &encoded_word.text,
+        }
+    }
+
+    /// Byte span of the segment in the header.
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Segment::ClearText(clear_text) => clear_text.span.clone(),
+            Segment::EncodedWord(encoded_word) => encoded_word.span.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ClearText, Decoder, EncodedWord, Encoding, Segment};
+
+    fn texts(encoded_str: &[u8]) -> Vec<String> {
+        crate::parse(encoded_str)
+            .unwrap()
+            .iter()
+            .map(|segment| segment.text().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn segments() {
+        assert_eq!(
+            crate::parse(b"a =?utf-8?B?w6k=?=\r\n =?UTF-8?q?b?= c").unwrap(),
+            vec![
+                Segment::ClearText(ClearText {
+                    text: "a ".to_string(),
+                    span: 0..2,
+                }),
+                Segment::EncodedWord(EncodedWord {
+                    charset: "utf-8".to_string(),
+                    canonical_charset: Some("UTF-8".to_string()),
+                    language: None,
+                    encoding: Some(Encoding::B),
+                    text: "é".to_string(),
+                    span: 2..18,
+                }),
+                Segment::EncodedWord(EncodedWord {
+                    charset: "UTF-8".to_string(),
+                    canonical_charset: Some("UTF-8".to_string()),
+                    language: None,
+                    encoding: Some(Encoding::Q),
+                    text: "b".to_string(),
+                    span: 21..34,
+                }),
+                Segment::ClearText(ClearText {
+                    text: " c".to_string(),
+                    span: 34..36,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn encoding_from_label() {
+        assert_eq!(Encoding::from_label(b""), Some(Encoding::Q));
+        assert_eq!(Encoding::from_label(b"q"), Some(Encoding::Q));
+        assert_eq!(Encoding::from_label(b"Quoted-Printable"), Some(Encoding::Q));
+        assert_eq!(Encoding::from_label(b"B"), Some(Encoding::B));
+        assert_eq!(Encoding::from_label(b"base64"), Some(Encoding::B));
+        assert_eq!(Encoding::from_label(b"bogus"), None);
+        assert_eq!(Encoding::from_label(b"x"), None);
+        assert_eq!(Encoding::from_label(b"\xff"), None);
+    }
+
+    #[test]
+    fn split_chars() {
+        assert_eq!(texts(b"=?UTF-8?B?ww==?= =?UTF-8?B?qWE=?="), vec!["", "éa"]);
+        assert_eq!(
+            texts(b"=?iso-2022-jp?B?GyRCRnw=?= =?iso-2022-jp?B?S1w4bBsoQg==?="),
+            vec!["日", "本語"]
+        );
+        assert_eq!(
+            texts(b"=?utf-8?B?ww==?= =?iso-8859-1?Q?=E9?="),
+            vec!["\u{FFFD}", "é"]
+        );
+    }
+
+    #[test]
+    fn canonical_charsets() {
+        let segments =
+            crate::parse(b"=?\"UTF_8\"?Q?a?= =?cp-850?Q?=82?= =?x-unknown?Q?b?=").unwrap();
+        let charsets = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::EncodedWord(encoded_word) => encoded_word.canonical_charset.as_deref(),
+                Segment::ClearText(_) => panic!("expected an encoded word"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(charsets, vec![Some("UTF-8"), Some("IBM850"), None]);
+        assert_eq!(
+            texts(b"=?utf-8?B?ww==?= =?\"UTF_8\"?B?qQ==?="),
+            vec!["", "é"]
+        );
+    }
+
+    #[test]
+    fn verbatim_words() {
+        assert_eq!(
+            crate::parse(b"=?utf-8?B?w6*k?= =?utf-8?Q?a?=").unwrap(),
+            vec![
+                Segment::EncodedWord(EncodedWord {
+                    charset: "utf-8".to_string(),
+                    canonical_charset: Some("UTF-8".to_string()),
+                    language: None,
+                    encoding: Some(Encoding::B),
+                    text: "=?utf-8?B?w6*k?=".to_string(),
+                    span: 0..16,
+                }),
+                Segment::ClearText(ClearText {
+                    text: " ".to_string(),
+                    span: 16..17,
+                }),
+                Segment::EncodedWord(EncodedWord {
+                    charset: "utf-8".to_string(),
+                    canonical_charset: Some("UTF-8".to_string()),
+                    language: None,
+                    encoding: Some(Encoding::Q),
+                    text: "a".to_string(),
+                    span: 17..30,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn recovered_words() {
+        let decoder = Decoder::new().recover_malformed_words(true);
+        let segments = decoder.parse(b"=?utf-8?q?a =?utf-8?q?b?=").unwrap();
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].text(), "=?utf-8?q?a ");
+        assert_eq!(segments[0].span(), 0..12);
+        assert_eq!(segments[1].text(), "b");
+        assert_eq!(segments[1].span(), 12..25);
+    }
+}
@@ -0,0 +1,140 @@
+/// Repair made to the encoded text of a B encoded word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Base64Repair {
+    /// The `=` padding was missing.
+    MissingPadding,
+    /// Whitespace, usually left by a broken folding, was removed.
+    Whitespace,
+    /// Characters following the encoded data were removed.
+    TrailingGarbage,
+    /// The `-` and `_` characters of the URL-safe alphabet were decoded
+    /// as `+` and `/`.
+    UrlSafeAlphabet,
+    /// A last incomplete character, which does not hold a whole byte,
+    /// was removed.
+    TruncatedChar,
+}
+
+fn decode_sextet(byte: u8) -> Option<(u8, bool)> {
+    match byte {
+        b'A'..=b'Z' => Some((byte - b'A', false)),
+        b'a'..=b'z' => Some((byte - b'a' + 26, false)),
+        b'0'..=b'9' => Some((byte - b'0' + 52, false)),
+        b'+' => Some((62, false)),
+        b'/' => Some((63, false)),
+        b'-' => Some((62, true)),
+        b'_' => Some((63, true)),
+        _ => None,
+    }
+}
+
+fn is_whitespace(byte: &u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n')
+}
+
+/// Decode base64 encoded text leniently, returning the decoded bytes
+/// with the repairs made to the encoded text.
+///
+/// Missing padding, whitespace, URL-safe characters and garbage after
+/// the encoded data are tolerated. Returns `None` when garbage is found
+/// in the middle of the encoded data, as nothing sensible can be decoded.
+pub fn decode_lenient(encoded_bytes: &[u8]) -> Option<(Vec<u8>, Vec<Base64Repair>)> {
+    let mut repairs = vec![];
+    let mut push_repair = |repair| {
+        if !repairs.contains(&repair) {
+            repairs.push(repair);
+        }
+    };
+
+    let mut sextets = Vec::with_capacity(encoded_bytes.len());
+    let mut data_end = encoded_bytes.len();
+
+    for (i, byte) in encoded_bytes.iter().enumerate() {
+        match decode_sextet(*byte) {
+            Some((sextet, url_safe)) => {
+                if url_safe {
+                    push_repair(Base64Repair::UrlSafeAlphabet);
+                }
+                sextets.push(sextet);
+            }
+            None if is_whitespace(byte) => push_repair(Base64Repair::Whitespace),
+            None => {
+                data_end = i;
+                break;
+            }
+        }
+    }
+
+    // Only padding and whitespace may follow the encoded data, anything
+    // else is garbage. Garbage followed by encoded data is not repaired.
+    let rest = &encoded_bytes[data_end..];
+    let padding_len = rest.iter().take_while(|b| **b == b'=').count();
+    if let Some(i) = rest[padding_len..].iter().position(|b| !is_whitespace(b)) {
+        let garbage = &rest[padding_len + i..];
+        if garbage.iter().any(|b| decode_sextet(*b).is_some()) && padding_len == 0 {
+            return None;
+        }
+        push_repair(Base64Repair::TrailingGarbage);
+    }
+
+    match sextets.len() % 4 {
+        0 => (),
+        1 => {
+            push_repair(Base64Repair::TruncatedChar);
+            sextets.pop();
+        }
+        len if padding_len < 4 - len => push_repair(Base64Repair::MissingPadding),
+        _ => (),
+    }
+
+    let mut decoded_bytes = Vec::with_capacity(sextets.len() * 3 / 4);
+    for chunk in sextets.chunks(4) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, sextet)| {
+            bits | u32::from(*sextet) << (18 - 6 * i)
+        });
+        let bytes = bits.to_be_bytes();
+        decoded_bytes.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+
+    Some((decoded_bytes, repairs))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::b_encoding::{self, Base64Repair::*};
+
+    fn assert_decoded(encoded_str: &str, decoded_str: &str, repairs: &[b_encoding::Base64Repair]) {
+        let (decoded_bytes, decoded_repairs) =
+            b_encoding::decode_lenient(encoded_str.as_bytes()).unwrap();
+        assert_eq!(String::from_utf8(decoded_bytes).unwrap(), decoded_str);
+        assert_eq!(decoded_repairs, repairs);
+    }
+
+    #[test]
+    fn decode_valid() {
+        assert_decoded("", "", &[]);
+        assert_decoded("c3Ry", "str", &[]);
+        assert_decoded("w6k=", "é", &[]);
+        assert_decoded("c3RyIHdpdGggc3BhY2Vz", "str with spaces", &[]);
+        assert_decoded("w6fDoMOf", "çàß", &[]);
+    }
+
+    #[test]
+    fn decode_repaired() {
+        assert_decoded("w6k", "é", &[MissingPadding]);
+        assert_decoded("w6k=", "é", &[]);
+        assert_decoded("w6\r\n k=", "é", &[Whitespace]);
+        assert_decoded("w6k=?garbage", "é", &[TrailingGarbage]);
+        assert_decoded("c3Ry!", "str", &[TrailingGarbage]);
+        assert_decoded("w6k= \t", "é", &[]);
+        assert_decoded("Pz8_", "???", &[UrlSafeAlphabet]);
+        assert_decoded("Pz8-Pz8", "??>??", &[UrlSafeAlphabet, MissingPadding]);
+        assert_decoded("c3RyI", "str", &[TruncatedChar]);
+    }
+
+    #[test]
+    fn decode_garbage() {
+        assert_eq!(b_encoding::decode_lenient(b"w6*k"), None);
+        assert_eq!(b_encoding::decode_lenient(b"c3Ry!c3Ry"), None);
+    }
+}
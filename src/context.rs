@@ -0,0 +1,136 @@
+use crate::lexer::{self, Token};
+use crate::Decoder;
+
+const MAX_ENCODED_WORD_LEN: usize = 75;
+
+/// Position of an encoded word in a header, as defined in RFC 2047
+/// section 5.
+///
+/// The position restricts the characters that may appear literally in
+/// the text of a Q encoded word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Context {
+    /// Unstructured header field body, like `Subject`.
+    #[default]
+    Text,
+    /// Comment of a structured header field, delimited by `(` and `)`.
+    Comment,
+    /// Phrase preceding an address, like the display name of `From`.
+    Phrase,
+}
+
+impl Context {
+    /// Tell whether the given byte may appear literally in the text of a
+    /// Q encoded word, `=` and `_` being reserved by the encoding itself.
+    pub(crate) fn allows_in_q(self, byte: u8) -> bool {
+        match self {
+            Context::Text => byte.is_ascii_graphic() && !matches!(byte, b'=' | b'?' | b'_'),
+            Context::Comment => {
+                Context::Text.allows_in_q(byte) && !matches!(byte, b'(' | b')' | b'"' | b'\\')
+            }
+            Context::Phrase => {
+                byte.is_ascii_alphanumeric() || matches!(byte, b'!' | b'*' | b'+' | b'-' | b'/')
+            }
+        }
+    }
+
+    /// Tell whether the given ASCII word may appear as clear text.
+    pub(crate) fn allows_in_clear(self, word: &str) -> bool {
+        match self {
+            Context::Text => true,
+            Context::Comment => !word.contains(&['(', ')', '\\'][..]),
+            Context::Phrase => word
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-/=?^_`{|}~.".contains(&b)),
+        }
+    }
+}
+
+fn is_token_char(byte: u8) -> bool {
+    byte.is_ascii_graphic() && !b"()<>@,;:\"/[]?.=".contains(&byte)
+}
+
+fn is_legal_q_text(encoded_text: &[u8], context: Context) -> bool {
+    let mut i = 0;
+
+    while i < encoded_text.len() {
+        match encoded_text[i] {
+            b'=' => match encoded_text.get(i + 1..i + 3) {
+                Some(hex) if hex.iter().all(u8::is_ascii_hexdigit) => i += 3,
+                _ => return false,
+            },
+            b'_' => i += 1,
+            b if context.allows_in_q(b) => i += 1,
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+fn is_legal_b_text(encoded_text: &[u8]) -> bool {
+    encoded_text.len().is_multiple_of(4) && base64::decode(encoded_text).is_ok()
+}
+
+/// Tell whether the given encoded word is legal in the given context.
+pub fn is_legal(encoded_word: &[u8], context: Context) -> bool {
+    if encoded_word.len() > MAX_ENCODED_WORD_LEN {
+        return false;
+    }
+
+    match lexer::run(encoded_word, &Decoder::default()).as_deref() {
+        Ok(
+            [(Token::Charset(charset), _), (Token::Encoding(encoding), _), (Token::EncodedText(encoded_text), _)],
+        ) => {
+            !charset.is_empty()
+                && charset.iter().all(|b| is_token_char(*b))
+                && match &encoding[..] {
+                    b"Q" | b"q" => is_legal_q_text(encoded_text, context),
+                    b"B" | b"b" => is_legal_b_text(encoded_text),
+                    _ => false,
+                }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{self, Context::*};
+
+    #[test]
+    fn text() {
+        assert!(context::is_legal(b"=?utf-8?Q?(a)_\"b\"=3F?=", Text));
+        assert!(context::is_legal(b"=?utf-8?b?w6fDoMOf?=", Text));
+        assert!(!context::is_legal(b"=?utf-8?Q?a b?=", Text));
+        assert!(!context::is_legal(b"=?utf-8?Q?a?b?=", Text));
+        assert!(!context::is_legal(b"=?utf-8?Q?a=3?=", Text));
+        assert!(!context::is_legal(b"=?utf-8?X?a?=", Text));
+        assert!(!context::is_legal(b"=?utf 8?Q?a?=", Text));
+        assert!(!context::is_legal(b"=?utf-8?B?w6fDoMO?=", Text));
+        assert!(!context::is_legal(b"a =?utf-8?Q?a?=", Text));
+    }
+
+    #[test]
+    fn comment() {
+        assert!(context::is_legal(b"=?utf-8?Q?a=28b=29?=", Comment));
+        assert!(!context::is_legal(b"=?utf-8?Q?a(b)?=", Comment));
+        assert!(!context::is_legal(b"=?utf-8?Q?\"a\"?=", Comment));
+        assert!(context::is_legal(b"=?utf-8?Q?a<b>?=", Comment));
+    }
+
+    #[test]
+    fn phrase() {
+        assert!(context::is_legal(b"=?utf-8?Q?J=C3=BCrgen_M!*+-/?=", Phrase));
+        assert!(!context::is_legal(b"=?utf-8?Q?a<b>?=", Phrase));
+        assert!(!context::is_legal(b"=?utf-8?Q?a.b?=", Phrase));
+    }
+
+    #[test]
+    fn too_long() {
+        let encoded_word = format!("=?utf-8?Q?{}?=", "a".repeat(63));
+        assert!(context::is_legal(encoded_word.as_bytes(), Phrase));
+        let encoded_word = format!("=?utf-8?Q?{}?=", "a".repeat(64));
+        assert!(!context::is_legal(encoded_word.as_bytes(), Phrase));
+    }
+}
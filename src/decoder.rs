@@ -0,0 +1,568 @@
+use crate::{
+    evaluator, filename, http, lexer, params, parser, rfc2231, CharsetRegistry, Decoded, Filename,
+    ParamHeader, Result, Segment,
+};
+
+/// What to do with encoded words whose encoded text cannot be decoded,
+/// and with clear text that is not valid UTF-8.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Keep the encoded word verbatim, delimiters and whitespace
+    /// separating it from adjacent encoded words included, as
+    /// recommended by RFC 2047 section 6.3. Invalid UTF-8 sequences of
+    /// clear text are replaced by U+FFFD REPLACEMENT CHARACTER.
+    #[default]
+    Verbatim,
+    /// Replace the encoded word, or each invalid UTF-8 sequence of clear
+    /// text, by U+FFFD REPLACEMENT CHARACTER.
+    Replace,
+    /// Drop the encoded word, or each invalid UTF-8 sequence of clear
+    /// text.
+    Drop,
+    /// Fail the whole decoding.
+    Fail,
+}
+
+/// RFC 2047 decoder.
+///
+/// ```rust
+/// use rfc2047_decoder::Decoder;
+///
+/// let decoder = Decoder::new().strict(true);
+///
+/// assert_eq!(decoder.decode(b"=?utf-8?q?str?= str").unwrap(), "str str");
+/// assert!(decoder.decode(b"str=?utf-8?q?str?=").is_err());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Decoder {
+    pub(crate) strict: bool,
+    pub(crate) recover_malformed_words: bool,
+    pub(crate) lenient_params: bool,
+    pub(crate) error_policy: ErrorPolicy,
+    pub(crate) strict_encoded_text: bool,
+    pub(crate) charsets: CharsetRegistry,
+    pub(crate) detect_charsets: bool,
+    pub(crate) detection_tld: Option<String>,
+    pub(crate) charset_fallbacks: Vec<String>,
+    pub(crate) clear_text_charset: Option<String>,
+    pub(crate) repair_mojibake: bool,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject headers that do not strictly conform to RFC 2047, instead
+    /// of decoding them on a best effort basis.
+    ///
+    /// Encoded words must be separated from surrounding text by
+    /// whitespace, or by the parentheses of a comment, must be at most
+    /// 75 characters long, must use the Q or B encoding, and their
+    /// encoded text must be made of printable ASCII characters other
+    /// than `?`. Otherwise, `base64` and `quoted-printable` are accepted
+    /// as encodings, and encoded words of other encodings cannot be
+    /// decoded, see [`Decoder::error_policy`].
+    ///
+    /// Strict mode implies [`Decoder::strict_encoded_text`].
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Keep the encoded words that fail to lex as clear text, instead
+    /// of failing the whole decoding.
+    ///
+    /// An encoded word is malformed when it is not terminated, when its
+    /// charset or its encoding contains whitespace, or when its encoding
+    /// is neither Q nor B. Its `=?` opening delimiter is then kept as
+    /// is, and lexing goes on right after it.
+    pub fn recover_malformed_words(mut self, recover_malformed_words: bool) -> Self {
+        self.recover_malformed_words = recover_malformed_words;
+        self
+    }
+
+    /// Decode RFC 2047 encoded words found in regular MIME parameter
+    /// values, like `name="=?utf-8?B?5pel5pys?="`.
+    ///
+    /// RFC 2047 forbids encoded words in quoted strings, but most mail
+    /// clients produce and decode them anyway.
+    pub fn lenient_params(mut self, lenient_params: bool) -> Self {
+        self.lenient_params = lenient_params;
+        self
+    }
+
+    /// Decode encoded text strictly, instead of repairing it.
+    ///
+    /// By default, B encoded text with missing padding, whitespace,
+    /// URL-safe characters or trailing garbage is repaired, see
+    /// [`crate::Base64Repair`], as is Q encoded text with lowercase or
+    /// truncated escapes, raw bytes, whitespace or soft line breaks, see
+    /// [`crate::QRepair`]. Each repair is reported as a
+    /// [`crate::Diagnostic`]. In strict mode, such encoded text cannot
+    /// be decoded, see [`Decoder::error_policy`]. This mode is always
+    /// enabled by [`Decoder::strict`].
+    pub fn strict_encoded_text(mut self, strict_encoded_text: bool) -> Self {
+        self.strict_encoded_text = strict_encoded_text;
+        self
+    }
+
+    /// Choose what to do with encoded words that cannot be decoded,
+    /// and with clear text that is not valid UTF-8. Defaults to
+    /// [`ErrorPolicy::Verbatim`].
+    ///
+    /// ```rust
+    /// use rfc2047_decoder::{Decoder, ErrorPolicy};
+    ///
+    /// let encoded_str = b"a =?utf-8?B?w6*k?= b";
+    ///
+    /// assert_eq!(Decoder::new().decode(encoded_str).unwrap(), "a =?utf-8?B?w6*k?= b");
+    /// assert_eq!(
+    ///     Decoder::new()
+    ///         .error_policy(ErrorPolicy::Replace)
+    ///         .decode(encoded_str)
+    ///         .unwrap(),
+    ///     "a \u{FFFD} b"
+    /// );
+    /// ```
+    pub fn error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Resolve charset labels with the given registry, to support
+    /// custom aliases and charsets. Defaults to [`CharsetRegistry::new`].
+    pub fn charset_registry(mut self, charsets: CharsetRegistry) -> Self {
+        self.charsets = charsets;
+        self
+    }
+
+    /// Guess the charset of encoded words whose charset label is
+    /// missing or unknown, like `unknown-8bit`, instead of decoding them
+    /// as US-ASCII.
+    ///
+    /// The guessed charset and the confidence in the guess are reported
+    /// in [`crate::Decoded::detected_charsets`].
+    ///
+    /// ```rust
+    /// use rfc2047_decoder::{Confidence, Decoder};
+    ///
+    /// let decoder = Decoder::new().detect_charsets(true);
+    /// let decoded = decoder
+    ///     .decode_with_diagnostics(b"=?unknown-8bit?Q?Caf=E9_cr=E8me_br=FBl=E9e?=")
+    ///     .unwrap();
+    ///
+    /// assert_eq!(decoded.text, "Café crème brûlée");
+    /// assert_eq!(decoded.detected_charsets[0].charset, "windows-1252");
+    /// ```
+    pub fn detect_charsets(mut self, detect_charsets: bool) -> Self {
+        self.detect_charsets = detect_charsets;
+        self
+    }
+
+    /// Hint charset detection with the top-level domain the header comes
+    /// from, like `ru` or `jp`, usually the one of the sender address.
+    /// Short texts are otherwise hard to tell apart.
+    pub fn detection_tld(mut self, tld: &str) -> Self {
+        self.detection_tld = Some(tld.to_string());
+        self
+    }
+
+    /// Decode encoded words whose bytes are invalid in their declared
+    /// charset, or implausible, like UTF-8 bytes claiming to be
+    /// ISO-8859-1, with the first of the given charsets they are valid
+    /// and plausible in. Unknown charsets are also decoded with the
+    /// fallback chain, unless [`Decoder::detect_charsets`] is enabled.
+    ///
+    /// The substituted charsets are reported in
+    /// [`crate::Decoded::substituted_charsets`].
+    ///
+    /// ```rust
+    /// use rfc2047_decoder::Decoder;
+    ///
+    /// let decoder = Decoder::new().charset_fallbacks(&["utf-8", "windows-1252"]);
+    ///
+    /// assert_eq!(decoder.decode(b"=?iso-8859-1?Q?caf=C3=A9?=").unwrap(), "café");
+    /// assert_eq!(decoder.decode(b"=?utf-8?Q?caf=E9?=").unwrap(), "café");
+    /// ```
+    pub fn charset_fallbacks(mut self, labels: &[&str]) -> Self {
+        self.charset_fallbacks = labels.iter().map(|label| label.to_string()).collect();
+        self
+    }
+
+    /// Decode clear text that is not valid UTF-8, like raw Latin-1 or
+    /// KOI8-R bytes sent by legacy mail clients, with the given charset,
+    /// usually the charset of the message body or a per-account default.
+    ///
+    /// Clear text that is not valid in this charset either is subject to
+    /// [`Decoder::error_policy`]. Whether the hint was used is reported
+    /// in [`crate::Decoded::charset_hint_used`].
+    ///
+    /// ```rust
+    /// use rfc2047_decoder::Decoder;
+    ///
+    /// let decoder = Decoder::new().clear_text_charset("koi8-r");
+    /// let decoded = decoder.decode_with_diagnostics(b"\xF0\xD2\xC9\xD7\xC5\xD4").unwrap();
+    ///
+    /// assert_eq!(decoded.text, "Привет");
+    /// assert!(decoded.charset_hint_used);
+    /// ```
+    pub fn clear_text_charset(mut self, label: &str) -> Self {
+        self.clear_text_charset = Some(label.to_string());
+        self
+    }
+
+    /// Repair segments whose decoded text is UTF-8 wrongly decoded as
+    /// windows-1252 or ISO-8859-1 upstream, then encoded again, like
+    /// `CafÃ©`.
+    ///
+    /// The repair is conservative: the text of the whole segment, or the
+    /// joined text of adjacent encoded words, must turn back into valid
+    /// UTF-8 and be less suspicious afterwards. Each repair is reported in
+    /// [`crate::Decoded::repaired_mojibake`].
+    ///
+    /// ```rust
+    /// use rfc2047_decoder::Decoder;
+    ///
+    /// let decoder = Decoder::new().repair_mojibake(true);
+    /// let encoded_str = b"=?utf-8?Q?Caf=C3=83=C2=A9_r=C3=83=C2=A9union?=";
+    ///
+    /// assert_eq!(decoder.decode(encoded_str).unwrap(), "Café réunion");
+    /// ```
+    pub fn repair_mojibake(mut self, repair_mojibake: bool) -> Self {
+        self.repair_mojibake = repair_mojibake;
+        self
+    }
+
+    /// Decode a RFC 2047 MIME Message Header.
+    ///
+    /// # Errors
+    ///
+    /// The function can return an error if the lexer,
+    /// the parser or the evaluator encounters an error.
+    pub fn decode(&self, encoded_str: &[u8]) -> Result<String> {
+        Ok(self.decode_with_diagnostics(encoded_str)?.text)
+    }
+
+    /// Find the RFC 2231 language tag of each encoded word of a RFC 2047
+    /// MIME Message Header, like `en-US` in `=?utf-8*en-US?Q?str?=`, in
+    /// order of appearance.
+    ///
+    /// ```rust
+    /// use rfc2047_decoder::Decoder;
+    ///
+    /// let languages = Decoder::new()
+    ///     .languages(b"=?utf-8*en-US?Q?str?= =?utf-8?Q?str?=")
+    ///     .unwrap();
+    ///
+    /// assert_eq!(languages, vec![Some("en-US".to_string()), None]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// The function can return an error if the lexer or the parser
+    /// encounters an error.
+    pub fn languages(&self, encoded_str: &[u8]) -> Result<Vec<Option<String>>> {
+        let tokens = lexer::run(encoded_str, self)?;
+        let ast = parser::run(&tokens, self)?;
+
+        Ok(ast
+            .into_iter()
+            .filter_map(|node| match node {
+                parser::Node::EncodedBytes(node) => Some(node.language),
+                parser::Node::ClearBytes(_) => None,
+            })
+            .map(|language| Some(String::from_utf8_lossy(&language?).to_string()))
+            .collect())
+    }
+
+    /// Decode a RFC 2047 MIME Message Header, with the problems met
+    /// while decoding it, see [`crate::decode_with_diagnostics`].
+    ///
+    /// # Errors
+    ///
+    /// The function can return an error if the lexer,
+    /// the parser or the evaluator encounters an error.
+    pub fn decode_with_diagnostics(&self, encoded_str: &[u8]) -> Result<Decoded> {
+        let tokens = lexer::run(encoded_str, self)?;
+        let ast = parser::run(&tokens, self)?;
+        let decoded = evaluator::run(&ast, encoded_str, self)?;
+
+        Ok(decoded)
+    }
+
+    /// Parse a RFC 2047 MIME Message Header into its clear text and
+    /// encoded word segments, see [`crate::parse`].
+    ///
+    /// # Errors
+    ///
+    /// The function can return an error if the lexer or the parser
+    /// encounters an error.
+    pub fn parse(&self, encoded_str: &[u8]) -> Result<Vec<Segment>> {
+        let tokens = lexer::run(encoded_str, self)?;
+        let ast = parser::run(&tokens, self)?;
+        let evaluated = evaluator::evaluate(&ast, encoded_str, self, &mut Decoded::default())?;
+
+        let mut segments = vec![];
+        for (node, mut evaluated) in ast.into_iter().zip(evaluated) {
+            if let Some(separator) = evaluated.separator.take() {
+                segments.push(Segment::ClearText(separator));
+            }
+            segments.push(Segment::new(node, evaluated));
+        }
+
+        Ok(segments)
+    }
+
+    /// Decode RFC 2231 MIME parameters, as names and unquoted values.
+    ///
+    /// # Errors
+    ///
+    /// The function can return an error if an extended value has no
+    /// charset or contains an invalid percent-encoded octet, and the
+    /// parameter has no regular value to fall back to.
+    pub fn decode_params<N: AsRef<str>, V: AsRef<[u8]>>(
+        &self,
+        params: &[(N, V)],
+    ) -> Result<Vec<(String, String)>> {
+        Ok(rfc2231::decode_params(params, self)?)
+    }
+
+    /// Parse and decode the value of a MIME header with parameters,
+    /// like `Content-Type` or `Content-Disposition`.
+    ///
+    /// # Errors
+    ///
+    /// The function can return an error if a parameter cannot be
+    /// decoded, see [`Decoder::decode_params`].
+    pub fn parse_params(&self, header_value: &[u8]) -> Result<ParamHeader> {
+        Ok(params::parse(header_value, self)?)
+    }
+
+    /// Parse and decode the value of a HTTP header with parameters, as
+    /// defined in RFC 8187. Regular values are decoded with this decoder
+    /// when they contain encoded words. An ext-value that cannot be
+    /// decoded, see [`crate::decode_ext_value`], is skipped and the
+    /// regular value of the parameter, if any, is used instead.
+    pub fn decode_http_params(&self, header_value: &[u8]) -> Result<ParamHeader> {
+        Ok(http::decode_params(header_value, self)?)
+    }
+
+    /// Decode and sanitise the filename of a `Content-Disposition`
+    /// header, or the name of a `Content-Type` header. Encoded words
+    /// are decoded in quoted values, whatever [`Decoder::lenient_params`].
+    ///
+    /// # Errors
+    ///
+    /// The function can return an error if a parameter cannot be
+    /// decoded, see [`Decoder::decode_params`].
+    pub fn decode_filename(&self, header_value: &[u8]) -> Result<Option<Filename>> {
+        filename::decode(header_value, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser, Decoder, Error};
+
+    fn assert_strict_err(encoded_str: &str) -> parser::Error {
+        assert!(Decoder::new().decode(encoded_str.as_bytes()).is_ok());
+
+        match Decoder::new().strict(true).decode(encoded_str.as_bytes()) {
+            Err(Error::Parser(err)) => err,
+            res => panic!("expected a parser error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn strict_ok() {
+        let decoder = Decoder::new().strict(true);
+        assert_eq!("", decoder.decode(b"").unwrap());
+        assert_eq!("a str b", decoder.decode(b"a =?utf-8?Q?str?= b").unwrap());
+        assert_eq!(
+            "strstr",
+            decoder
+                .decode(b"=?UTF-8?Q?str?=\r\n =?UTF-8?b?c3Ry?=")
+                .unwrap()
+        );
+        assert_eq!(
+            "strstr",
+            decoder
+                .decode(b"=?UTF-8?Q?str?=\r\n\t=?UTF-8?b?c3Ry?=")
+                .unwrap()
+        );
+
+        // Examples of RFC 2047 section 8.
+        for (encoded_str, decoded_str) in &[
+            ("(=?ISO-8859-1?Q?a?=)", "(a)"),
+            ("(=?ISO-8859-1?Q?a?= b)", "(a b)"),
+            ("(=?ISO-8859-1?Q?a?= =?ISO-8859-1?Q?b?=)", "(ab)"),
+            ("(=?ISO-8859-1?Q?a?=  =?ISO-8859-1?Q?b?=)", "(ab)"),
+            ("(=?ISO-8859-1?Q?a?=\r\n    =?ISO-8859-1?Q?b?=)", "(ab)"),
+            ("(=?ISO-8859-1?Q?a_b?=)", "(a b)"),
+            ("(=?ISO-8859-1?Q?a?= =?ISO-8859-2?Q?_b?=)", "(a b)"),
+        ] {
+            assert_eq!(
+                decoder.decode(encoded_str.as_bytes()).unwrap(),
+                *decoded_str
+            );
+        }
+    }
+
+    #[test]
+    fn strict_not_separated() {
+        for encoded_str in &[
+            "abc=?utf-8?q?x?=def",
+            "abc=?utf-8?q?x?=",
+            "=?utf-8?q?x?=def",
+            "=?utf-8?q?x?==?utf-8?q?x?=",
+            ")=?utf-8?q?x?=)",
+            "(=?utf-8?q?x?=(",
+        ] {
+            assert!(matches!(
+                assert_strict_err(encoded_str),
+                parser::Error::EncodedWordNotSeparatedError(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn strict_too_long() {
+        let encoded_str = format!("=?utf-8?q?{}?=", "a".repeat(64));
+        assert!(matches!(
+            assert_strict_err(&encoded_str),
+            parser::Error::EncodedWordTooLongError(76, _)
+        ));
+    }
+
+    #[test]
+    fn strict_invalid_encoded_text() {
+        for encoded_str in &["=?utf-8?q?a b?=", "=?utf-8?q?a?b?=", "=?utf-8?q?a\tb?="] {
+            assert!(matches!(
+                assert_strict_err(encoded_str),
+                parser::Error::InvalidEncodedTextError(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn strict_unknown_encoding() {
+        for encoded_str in &["=?utf-8?base64?c3Ry?=", "=?utf-8??str?="] {
+            assert!(matches!(
+                assert_strict_err(encoded_str),
+                parser::Error::UnknownEncodingError(..)
+            ));
+        }
+    }
+
+    #[test]
+    fn unknown_encoding() {
+        for encoded_str in &["=?utf-8?x?str?=", "a =?utf-8?bogus?c3Ry?= b"] {
+            assert!(matches!(
+                assert_strict_err(encoded_str),
+                parser::Error::UnknownEncodingError(..)
+            ));
+            assert_eq!(
+                Decoder::new().decode(encoded_str.as_bytes()).unwrap(),
+                *encoded_str
+            );
+        }
+
+        let decoded = Decoder::new()
+            .decode_with_diagnostics(b"=?utf-8?x?str?=")
+            .unwrap();
+        assert_eq!(
+            decoded.diagnostics[0].kind,
+            crate::DiagnosticKind::UnknownEncoding("x".to_string())
+        );
+
+        let decoder = Decoder::new().recover_malformed_words(true);
+        assert_eq!(
+            decoder.decode(b"Re: =?utf-8?x?str?= hi").unwrap(),
+            "Re: =?utf-8?x?str?= hi"
+        );
+    }
+
+    fn assert_location(encoded_str: &str, offset: usize, encoded_word: &str) {
+        let err = Decoder::new()
+            .strict(true)
+            .decode(encoded_str.as_bytes())
+            .unwrap_err();
+        let location = err.location().unwrap();
+
+        assert_eq!(location.offset, offset);
+        assert_eq!(location.encoded_word, encoded_word);
+    }
+
+    #[test]
+    fn error_locations() {
+        assert_location("a =?utf-8?q?b", 13, "=?utf-8?q?b");
+        assert_location("a =?utf-8 b", 11, "=?utf-8 b");
+        assert_location("abc=?utf-8?q?x?=", 3, "=?utf-8?q?x?=");
+        assert_location("=?utf-8?q?x?=def", 13, "=?utf-8?q?x?=");
+        assert_location("a =?utf-8?x?str?=", 10, "=?utf-8?x?str?=");
+        assert_location("a =?utf-8?q?a b?=", 13, "=?utf-8?q?a b?=");
+        assert_location(
+            &format!("a =?utf-8?q?{}?=", "a".repeat(64)),
+            77,
+            &format!("=?utf-8?q?{}?=", "a".repeat(64)),
+        );
+    }
+
+    #[test]
+    fn error_location_non_utf8() {
+        let err = Decoder::new()
+            .strict(true)
+            .decode(b"=?\xff?x?a?=")
+            .unwrap_err();
+
+        assert_eq!(
+            err.location().unwrap().to_string(),
+            "at byte 4\n  =?\u{fffd}?x?a?=\n      ^"
+        );
+    }
+
+    #[test]
+    fn error_display() {
+        let err = Decoder::new()
+            .strict(true)
+            .decode(b"Subject =?utf-8?q?a b?=")
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "the encoded text contains whitespace, `?` or non printable characters at byte 19\n  =?utf-8?q?a b?=\n             ^"
+        );
+    }
+
+    #[test]
+    fn recover_malformed_words() {
+        let decoder = Decoder::new().recover_malformed_words(true);
+        assert_eq!("Price =? ask", decoder.decode(b"Price =? ask").unwrap());
+        assert_eq!(
+            "str =?utf-8?q?tr",
+            decoder.decode(b"=?utf-8?q?str?= =?utf-8?q?tr").unwrap()
+        );
+        assert_eq!(
+            "=?utf 8?q?str?= str",
+            decoder.decode(b"=?utf 8?q?str?= =?utf-8?q?str?=").unwrap()
+        );
+    }
+
+    #[test]
+    fn recover_malformed_words_padding() {
+        let decoder = Decoder::new().recover_malformed_words(true);
+        assert_eq!("a", decoder.decode(b"=?utf-8?B?YQ==?=").unwrap());
+        assert_eq!("ab", decoder.decode(b"=?utf-8?B?YWI=?=").unwrap());
+        assert_eq!(
+            "ab c",
+            decoder
+                .decode(b"=?utf-8?B?YQ==?= =?utf-8?B?Yg==?= c")
+                .unwrap()
+        );
+        assert_eq!(
+            "=?utf-8?B?YQ==ab",
+            decoder.decode(b"=?utf-8?B?YQ===?utf-8?B?YWI=?=").unwrap()
+        );
+    }
+}
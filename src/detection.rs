@@ -0,0 +1,99 @@
+use std::ops::Range;
+
+use chardetng::EncodingDetector;
+use charset::Charset;
+
+use crate::registry::ResolvedCharset;
+
+/// Confidence in a charset guessed by statistical detection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Confidence {
+    /// The guess is likely right.
+    High,
+    /// The bytes are too short or too ambiguous for the guess to be
+    /// reliable.
+    Low,
+}
+
+/// Charset guessed for encoded words whose charset label is missing or
+/// unknown, see [`crate::Decoder::detect_charsets`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetectedCharset {
+    /// Charset label, as written in the encoded words.
+    pub label: String,
+    /// Canonical name of the guessed charset, like `windows-1251`.
+    pub charset: String,
+    /// Confidence in the guess.
+    pub confidence: Confidence,
+    /// Byte span of the encoded words sharing the label in the header.
+    pub span: Range<usize>,
+}
+
+/// Normalise a top-level domain the way the detector expects it,
+/// without leading dot and lowercased.
+fn normalize_tld(tld: &str) -> Option<String> {
+    let tld = tld.trim().trim_start_matches('.').to_ascii_lowercase();
+    if !tld.is_empty() && tld.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+        Some(tld)
+    } else {
+        None
+    }
+}
+
+/// Guess the charset of the given decoded bytes, using the given
+/// top-level domain as a hint.
+pub(crate) fn detect(decoded_bytes: &[u8], tld: Option<&str>) -> (ResolvedCharset, Confidence) {
+    let tld = tld.and_then(normalize_tld);
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(decoded_bytes, true);
+    let (encoding, high_confidence) =
+        detector.guess_assess(tld.as_ref().map(|tld| tld.as_bytes()), true);
+
+    let confidence = if high_confidence {
+        Confidence::High
+    } else {
+        Confidence::Low
+    };
+
+    (
+        ResolvedCharset::builtin(Charset::for_encoding(encoding)),
+        confidence,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detection::{self, Confidence};
+
+    fn detect(decoded_bytes: &[u8], tld: Option<&str>) -> (String, Confidence) {
+        let (charset, confidence) = detection::detect(decoded_bytes, tld);
+        (charset.name().to_string(), confidence)
+    }
+
+    #[test]
+    fn detect_charsets() {
+        assert_eq!(detect("Привет, как дела?".as_bytes(), None).0, "UTF-8");
+        assert_eq!(
+            detect(
+                b"\xcf\xf0\xe8\xe2\xe5\xf2, \xea\xe0\xea \xe4\xe5\xeb\xe0?",
+                None
+            )
+            .0,
+            "windows-1251"
+        );
+        assert_eq!(
+            detect(b"Caf\xe9 cr\xe8me br\xfbl\xe9e", None).0,
+            "windows-1252"
+        );
+    }
+
+    #[test]
+    fn detect_with_tld() {
+        // Too short to be told apart without a hint.
+        assert_eq!(detect(b"\xe4\xe0", None).0, "windows-1252");
+        assert_eq!(detect(b"\xe4\xe0", Some("jp")).0, "Shift_JIS");
+        assert_eq!(detect(b"\xe4\xe0", Some(".JP")).0, "Shift_JIS");
+        assert_eq!(detect(b"\xe4\xe0", Some("not a tld")).0, "windows-1252");
+    }
+}
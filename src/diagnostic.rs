@@ -0,0 +1,92 @@
+use std::ops::Range;
+
+use crate::{Base64Repair, DetectedCharset, QRepair, RepairedMojibake, SubstitutedCharset};
+
+/// Kind of problem met while decoding a header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// The charset label of an encoded word is unknown.
+    UnknownCharset(String),
+    /// The decoded bytes of an encoded word are invalid in its charset.
+    InvalidCharsetBytes(String),
+    /// The decoded bytes of an encoded word are valid in its charset, but
+    /// unlikely to be in this charset, see [`crate::Decoder::charset_fallbacks`].
+    ImplausibleCharsetBytes(String),
+    /// The encoding of an encoded word is neither Q nor B.
+    UnknownEncoding(String),
+    /// The encoded text of a B encoded word is not valid base64.
+    InvalidBase64,
+    /// The encoded text of a B encoded word was repaired.
+    RepairedBase64(Base64Repair),
+    /// The encoded text of a Q encoded word is malformed.
+    InvalidQEscape,
+    /// The encoded text of a Q encoded word was repaired.
+    RepairedQ(QRepair),
+    /// The clear text is not valid UTF-8.
+    InvalidUtf8,
+    /// The decoded text of a segment is UTF-8 wrongly decoded as
+    /// windows-1252 or ISO-8859-1, see [`crate::Decoder::repair_mojibake`].
+    Mojibake,
+}
+
+/// Fallback applied to the text a diagnostic is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fallback {
+    /// The bytes were decoded as US-ASCII, other bytes being replaced by
+    /// U+FFFD REPLACEMENT CHARACTER.
+    Ascii,
+    /// The invalid bytes were replaced by U+FFFD REPLACEMENT CHARACTER.
+    ReplacementChar,
+    /// The encoded word was kept verbatim, delimiters included.
+    EncodedWord,
+    /// The encoded word, or the invalid bytes, were dropped.
+    Dropped,
+    /// The encoded text was repaired, then decoded, or the decoded text
+    /// was repaired.
+    Repaired,
+    /// The bytes were decoded with a charset guessed by statistical
+    /// detection, see [`crate::Decoder::detect_charsets`].
+    Detected,
+    /// The bytes were decoded with the first charset of the fallback
+    /// chain they are valid and plausible in, see
+    /// [`crate::Decoder::charset_fallbacks`].
+    NextCharset,
+    /// The clear text was decoded with the charset hint, see
+    /// [`crate::Decoder::clear_text_charset`].
+    CharsetHint,
+}
+
+/// Problem met while decoding a header, which did not prevent decoding
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// What went wrong.
+    pub kind: DiagnosticKind,
+    /// Byte span of the encoded word, of the encoded words sharing the
+    /// same charset, or of the clear text in the header.
+    pub span: Range<usize>,
+    /// What was done instead.
+    pub fallback: Fallback,
+}
+
+/// Decoded header, with the problems met while decoding it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Decoded {
+    /// Decoded text.
+    pub text: String,
+    /// Problems met while decoding, in order of appearance. Empty when
+    /// the header decoded cleanly.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Charsets guessed for encoded words whose charset label is missing
+    /// or unknown, in order of appearance, when detection is enabled.
+    pub detected_charsets: Vec<DetectedCharset>,
+    /// Charsets substituted for the declared charsets of encoded words,
+    /// in order of appearance, when a fallback chain is configured.
+    pub substituted_charsets: Vec<SubstitutedCharset>,
+    /// Whether clear text that is not valid UTF-8 was decoded with the
+    /// charset hint of the decoder.
+    pub charset_hint_used: bool,
+    /// Mojibake repaired in the decoded segments, in order of
+    /// appearance, when the repair is enabled.
+    pub repaired_mojibake: Vec<RepairedMojibake>,
+}
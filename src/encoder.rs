@@ -0,0 +1,659 @@
+use crate::{ast::Encoding, context::Context, rfc2231};
+
+const MAX_ENCODED_WORD_LEN: usize = 75;
+const MAX_LINE_LEN: usize = 76;
+
+const SPACE: u8 = b' ';
+const UNDERSCORE: u8 = b'_';
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Charset {
+    UsAscii,
+    Iso88591,
+    Utf8,
+}
+
+impl Charset {
+    /// Find the smallest charset able to represent the given string.
+    ///
+    /// ISO-8859-1 is decoded as windows-1252 by the evaluator, so
+    /// strings containing C1 controls need UTF-8 to round-trip.
+    fn for_str(decoded_str: &str) -> Self {
+        if decoded_str.is_ascii() {
+            Charset::UsAscii
+        } else if decoded_str
+            .chars()
+            .all(|c| c < '\u{80}' || ('\u{A0}'..='\u{FF}').contains(&c))
+        {
+            Charset::Iso88591
+        } else {
+            Charset::Utf8
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Charset::UsAscii => "us-ascii",
+            Charset::Iso88591 => "iso-8859-1",
+            Charset::Utf8 => "utf-8",
+        }
+    }
+
+    fn encode_char(self, c: char, bytes: &mut Vec<u8>) {
+        match self {
+            Charset::Iso88591 => bytes.push(c as u8),
+            Charset::UsAscii | Charset::Utf8 => {
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes())
+            }
+        }
+    }
+}
+
+/// A piece of the string to encode, along with the whitespace
+/// preceding it.
+#[derive(Debug)]
+enum Run<'a> {
+    Clear(&'a str, &'a str),
+    Encoded(&'a str, &'a str, Charset),
+}
+
+fn encode_quoted_printable(context: Context, decoded_bytes: &[u8]) -> String {
+    let mut encoded_str = String::new();
+
+    for byte in decoded_bytes {
+        match *byte {
+            SPACE => encoded_str.push(UNDERSCORE as char),
+            b if context.allows_in_q(b) => encoded_str.push(b as char),
+            b => encoded_str.push_str(&format!("={:02X}", b)),
+        }
+    }
+
+    encoded_str
+}
+
+fn base64_len(decoded_bytes: &[u8]) -> usize {
+    decoded_bytes.len().div_ceil(3) * 4
+}
+
+fn encoded_text_len(context: Context, decoded_bytes: &[u8]) -> usize {
+    let q_len = encode_quoted_printable(context, decoded_bytes).len();
+    q_len.min(base64_len(decoded_bytes))
+}
+
+/// Encode the given bytes with the given encoding, the inverse of
+/// `evaluator::decode_with_encoding`.
+pub fn encode_with_encoding(encoding: Encoding, context: Context, decoded_bytes: &[u8]) -> String {
+    match encoding {
+        Encoding::B => base64::encode(decoded_bytes),
+        Encoding::Q => encode_quoted_printable(context, decoded_bytes),
+    }
+}
+
+fn encode_word(context: Context, charset: Charset, decoded_bytes: &[u8]) -> String {
+    let q_len = encode_quoted_printable(context, decoded_bytes).len();
+    let encoding = if q_len <= base64_len(decoded_bytes) {
+        Encoding::Q
+    } else {
+        Encoding::B
+    };
+    let encoded_text = encode_with_encoding(encoding, context, decoded_bytes);
+
+    format!("=?{}?{}?{}?=", charset.label(), encoding, encoded_text)
+}
+
+/// Split off the longest prefix of the given string that fits in an
+/// encoded word of at most `max_word_len` characters.
+///
+/// Returns the encoded word and the remaining string, or `None` if not
+/// even the first character fits.
+fn split_word(
+    decoded_str: &str,
+    context: Context,
+    charset: Charset,
+    max_word_len: usize,
+) -> Option<(String, &str)> {
+    let overhead = "=?".len() + charset.label().len() + "?Q?".len() + "?=".len();
+    let max_text_len = max_word_len.checked_sub(overhead)?;
+    let mut decoded_bytes = vec![];
+    let mut prefix_len = 0;
+    let mut end = 0;
+
+    for (i, c) in decoded_str.char_indices() {
+        charset.encode_char(c, &mut decoded_bytes);
+        if encoded_text_len(context, &decoded_bytes) > max_text_len {
+            break;
+        }
+        prefix_len = decoded_bytes.len();
+        end = i + c.len_utf8();
+    }
+
+    if end == 0 {
+        None
+    } else {
+        let word = encode_word(context, charset, &decoded_bytes[..prefix_len]);
+        Some((word, &decoded_str[end..]))
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// RFC 2047 encoder.
+///
+/// ```rust
+/// use rfc2047_decoder::Encoder;
+///
+/// let encoder = Encoder::new().minimal(true);
+///
+/// assert_eq!(
+///     encoder.encode("Re: Budget für 2026"),
+///     "Re: Budget =?iso-8859-1?B?Zvxy?= 2026"
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Encoder {
+    minimal: bool,
+    context: Context,
+    legacy_params: bool,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only encode the words that need to be, and leave the others as
+    /// clear text.
+    ///
+    /// Words containing non-ASCII characters, control characters or
+    /// something that looks like an encoded word are encoded. Adjacent
+    /// ones are merged into the same encoded words, using the smallest
+    /// charset among US-ASCII, ISO-8859-1 and UTF-8.
+    pub fn minimal(mut self, minimal: bool) -> Self {
+        self.minimal = minimal;
+        self
+    }
+
+    /// Set the position of the encoded words in the header, which
+    /// restricts the characters they may contain. In minimal mode, clear
+    /// words not allowed in that position are encoded too.
+    pub fn context(mut self, context: Context) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Along with the RFC 2231 form of non-ASCII parameters, emit a
+    /// regular parameter whose quoted value is made of RFC 2047 encoded
+    /// words, for clients not supporting RFC 2231.
+    pub fn legacy_params(mut self, legacy_params: bool) -> Self {
+        self.legacy_params = legacy_params;
+        self
+    }
+
+    fn needs_encoding(&self, word: &str, max_clear_len: usize) -> bool {
+        !word.is_ascii()
+            || !self.context.allows_in_clear(word)
+            || word.len() > max_clear_len
+            || word.contains("=?")
+            || word.contains("?=")
+            || word.bytes().any(|b| b.is_ascii_control())
+    }
+
+    /// Length of the encoded word of the first char of the given word,
+    /// the shortest piece of it that can start a line.
+    fn first_char_word_len(&self, word: &str) -> usize {
+        let charset = Charset::for_str(word);
+        let mut decoded_bytes = vec![];
+        if let Some(c) = word.chars().next() {
+            charset.encode_char(c, &mut decoded_bytes);
+        }
+        encode_word(self.context, charset, &decoded_bytes).len()
+    }
+
+    /// Split the given string into runs of clear and encoded words, so
+    /// that each clear word fits on a line of `max_line_len` characters
+    /// along with its preceding whitespace. The first line is considered
+    /// to already contain `first_line_len` characters.
+    fn runs<'a>(
+        &self,
+        decoded_str: &'a str,
+        first_line_len: usize,
+        max_line_len: usize,
+    ) -> Vec<Run<'a>> {
+        if decoded_str.is_empty() {
+            return vec![];
+        }
+
+        if !self.minimal {
+            return vec![Run::Encoded("", decoded_str, Charset::Utf8)];
+        }
+
+        // Runs as (whitespace start, text start, text end, encoded).
+        let mut bounds: Vec<(usize, usize, usize, bool)> = vec![];
+        let mut rest = decoded_str;
+
+        while !rest.is_empty() {
+            let ws_start = decoded_str.len() - rest.len();
+            let mut text_start =
+                ws_start + (rest.len() - rest.trim_start_matches(is_whitespace).len());
+            rest = &decoded_str[text_start..];
+            let end = text_start + rest.find(is_whitespace).unwrap_or(rest.len());
+            rest = &decoded_str[end..];
+
+            // Nothing can be folded before the first word.
+            let line_len = if ws_start == 0 { first_line_len } else { 0 };
+            let ws_len = text_start - ws_start;
+            let word = &decoded_str[text_start..end];
+            let room = max_line_len.saturating_sub(line_len + ws_len);
+            let mut encoded = !word.is_empty() && self.needs_encoding(word, room);
+
+            // Whitespace too long to fit on a line along with the start
+            // of the word is encoded with it, but for a separator.
+            let head_len = match word {
+                "" => 0,
+                word if encoded => self.first_char_word_len(word),
+                word => word.len(),
+            };
+            let separator_len = if ws_start == 0 { 0 } else { 1 };
+            if ws_len > separator_len && ws_len + head_len > max_line_len.saturating_sub(line_len) {
+                encoded = true;
+                text_start = ws_start + separator_len;
+            }
+
+            match bounds.last_mut() {
+                Some((_, _, last_end, true)) if encoded => *last_end = end,
+                _ => bounds.push((ws_start, text_start, end, encoded)),
+            }
+        }
+
+        bounds
+            .into_iter()
+            .map(|(ws_start, text_start, end, encoded)| {
+                let ws = &decoded_str[ws_start..text_start];
+                let text = &decoded_str[text_start..end];
+
+                if encoded {
+                    Run::Encoded(ws, text, Charset::for_str(text))
+                } else {
+                    Run::Clear(ws, text)
+                }
+            })
+            .collect()
+    }
+
+    /// Write the given runs, folding lines longer than `max_line_len`
+    /// characters. The first line is considered to already contain
+    /// `line_len` characters.
+    fn write(&self, runs: &[Run], mut line_len: usize, max_line_len: usize) -> String {
+        let mut output = String::new();
+
+        for run in runs {
+            match *run {
+                Run::Clear(ws, word) => {
+                    if !output.is_empty()
+                        && !ws.is_empty()
+                        && line_len + ws.len() + word.len() > max_line_len
+                    {
+                        output.push_str("\r\n");
+                        line_len = 0;
+                    }
+                    output.push_str(ws);
+                    output.push_str(word);
+                    line_len += ws.len() + word.len();
+                }
+                Run::Encoded(ws, text, charset) => {
+                    let mut separator = ws;
+                    let mut rest = text;
+
+                    while !rest.is_empty() {
+                        let room = max_line_len
+                            .saturating_sub(line_len + separator.len())
+                            .min(MAX_ENCODED_WORD_LEN);
+
+                        match split_word(rest, self.context, charset, room) {
+                            Some((word, tail)) => {
+                                output.push_str(separator);
+                                output.push_str(&word);
+                                line_len += separator.len() + word.len();
+                                separator = " ";
+                                rest = tail;
+                            }
+                            // Nothing can be folded before the first word,
+                            // which then goes on the first line, however
+                            // long it gets.
+                            None if output.is_empty() => {
+                                let (word, tail) = (room + 1..=MAX_ENCODED_WORD_LEN)
+                                    .find_map(|room| split_word(rest, self.context, charset, room))
+                                    .expect("a char always fits in an encoded word");
+                                output.push_str(separator);
+                                output.push_str(&word);
+                                line_len += separator.len() + word.len();
+                                separator = " ";
+                                rest = tail;
+                            }
+                            None => {
+                                output.push_str("\r\n");
+                                line_len = 0;
+                                if separator.is_empty() {
+                                    separator = " ";
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Encode the given string into RFC 2047 encoded words.
+    ///
+    /// The string is split on character boundaries so that every encoded
+    /// word, delimiters included, fits in 75 characters. Each word uses
+    /// whichever of the Q or B encoding gives the shortest result, and
+    /// consecutive words are separated by a space.
+    pub fn encode(&self, decoded_str: &str) -> String {
+        let runs = self.runs(decoded_str, 0, usize::MAX);
+        self.write(&runs, 0, usize::MAX)
+    }
+
+    /// Encode the given string as the folded value of the given header
+    /// field.
+    ///
+    /// Lines, the `field_name: ` prefix included on the first one, are
+    /// at most 76 characters long and are folded with a CRLF followed by
+    /// whitespace. In minimal mode, clear words too long to fit on their
+    /// line are encoded, as are runs of whitespace too long to fit on a
+    /// line, and lines may be folded between clear words, which the
+    /// decoder unfolds.
+    pub fn encode_header(&self, field_name: &str, decoded_str: &str) -> String {
+        let line_len = field_name.len() + ": ".len();
+        let runs = self.runs(decoded_str, line_len, MAX_LINE_LEN);
+        self.write(&runs, line_len, MAX_LINE_LEN)
+    }
+
+    /// Encode the given MIME parameter, like the `filename` of a
+    /// `Content-Disposition` header.
+    ///
+    /// The parameter is encoded as defined in RFC 2231, and split into
+    /// continuations when too long. Each returned item is meant to be
+    /// on its own line, so they should be joined with `;\r\n `.
+    pub fn encode_param(&self, name: &str, value: &str) -> Vec<String> {
+        let mut params = vec![];
+
+        if self.legacy_params && !value.is_ascii() {
+            // Encoded words in comments cannot contain `"` nor `\`, so
+            // they are safe in quoted strings too.
+            let encoder = Encoder::new().context(Context::Comment);
+            let runs = [Run::Encoded("", value, Charset::Utf8)];
+            let prefix = format!("{}=\"", name);
+            let line_len = " ".len() + prefix.len();
+            let encoded_words = encoder.write(&runs, line_len, MAX_LINE_LEN - "\";".len());
+            params.push(format!("{}{}\"", prefix, encoded_words));
+        }
+
+        params.extend(rfc2231::encode_param(name, value));
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{decode, encoder, evaluator, is_legal_encoded_word, Context, Encoder, Encoding};
+
+    fn assert_round_trip(decoded_str: &str) {
+        let encoded_str = Encoder::new().encode(decoded_str);
+        assert_eq!(decode(encoded_str.as_bytes()).unwrap(), decoded_str);
+
+        for word in encoded_str.split(' ').filter(|w| !w.is_empty()) {
+            assert!(word.len() <= 75, "word too long: {}", word);
+            // Each word must decode on its own, which means no
+            // character has been split across two words.
+            assert!(!decode(word.as_bytes()).unwrap().contains('\u{FFFD}'));
+        }
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!("", Encoder::new().encode(""));
+    }
+
+    #[test]
+    fn q_or_b() {
+        assert_eq!(
+            "=?utf-8?Q?str_with_spaces?=",
+            Encoder::new().encode("str with spaces")
+        );
+        assert_eq!("=?utf-8?B?w6fDoMOf?=", Encoder::new().encode("çàß"));
+    }
+
+    #[test]
+    fn trailing_space() {
+        assert_eq!("=?utf-8?Q?str_?=", Encoder::new().encode("str "));
+        assert_round_trip("str ");
+    }
+
+    #[test]
+    fn special_chars() {
+        assert_round_trip("?= =?utf-8?q?x?= _=\t\r\n");
+        assert_round_trip("str with special çhàrß");
+    }
+
+    #[test]
+    fn long_str() {
+        assert_round_trip(&"a".repeat(200));
+        assert_round_trip(&"é".repeat(100));
+        assert_round_trip(&"日本語のテキスト 🦀 ".repeat(20));
+    }
+
+    fn assert_folded(field_name: &str, decoded_str: &str) {
+        for encoder in [Encoder::new(), Encoder::new().minimal(true)] {
+            let folded_str = encoder.encode_header(field_name, decoded_str);
+            assert_eq!(decode(folded_str.as_bytes()).unwrap(), decoded_str);
+
+            let header = format!("{}: {}", field_name, folded_str);
+            for line in header.split("\r\n") {
+                assert!(line.len() <= 76, "line too long: {:?}", line);
+            }
+            for line in header.split("\r\n").skip(1) {
+                assert!(line.starts_with(' ') && !line.starts_with("  "));
+            }
+        }
+    }
+
+    #[test]
+    fn fold_short() {
+        assert_eq!(
+            "=?utf-8?Q?str_with_spaces?=",
+            Encoder::new().encode_header("Subject", "str with spaces")
+        );
+        assert_eq!("", Encoder::new().encode_header("Subject", ""));
+    }
+
+    #[test]
+    fn fold_long() {
+        assert_folded("Subject", &"str with spaces ".repeat(20));
+        assert_folded("Subject", &"çhàrß ".repeat(30));
+        assert_folded("X-Very-Long-Header-Field-Name", &"日本語 🦀".repeat(15));
+    }
+
+    #[test]
+    fn fold_long_clear_text() {
+        assert_folded("Subject", &"x".repeat(70));
+        assert_folded("Subject", &format!("a{}b", " ".repeat(82)));
+        assert_folded("Subject", &format!("{}é", " ".repeat(80)));
+        assert_folded("Subject", &format!("a{}é", "\t".repeat(80)));
+        assert_folded("Subject", &format!("a{}", " ".repeat(100)));
+        assert_folded("Subject", &" ".repeat(100));
+    }
+
+    #[test]
+    fn fold_long_field_name() {
+        let field_name = format!("X-{}-Onward", "Very-Long-Field-Name".repeat(3));
+        let decoded_str = "Jürgen Müller wrote about the budget for 2026";
+
+        for encoder in [Encoder::new(), Encoder::new().minimal(true)] {
+            let folded_str = encoder.encode_header(&field_name, decoded_str);
+            assert!(folded_str.starts_with("=?"), "{:?}", folded_str);
+            assert_eq!(decode(folded_str.as_bytes()).unwrap(), decoded_str);
+        }
+    }
+
+    #[test]
+    fn fold_packs_words() {
+        let folded_str = Encoder::new().encode_header("Subject", &"a".repeat(100));
+        let lines = folded_str.split("\r\n").collect::<Vec<_>>();
+        assert_eq!(2, lines.len());
+        assert_eq!(76, "Subject: ".len() + lines[0].len());
+    }
+
+    fn assert_minimal(encoded_str: &str, decoded_str: &str) {
+        let encoder = Encoder::new().minimal(true);
+        assert_eq!(encoder.encode(decoded_str), encoded_str);
+        assert_eq!(decode(encoded_str.as_bytes()).unwrap(), decoded_str);
+
+        let folded_str = encoder.encode_header("Subject", decoded_str);
+        assert_eq!(decode(folded_str.as_bytes()).unwrap(), decoded_str);
+        for line in format!("Subject: {}", folded_str).split("\r\n") {
+            assert!(line.len() <= 76, "line too long: {}", line);
+        }
+    }
+
+    #[test]
+    fn minimal() {
+        assert_minimal("", "");
+        assert_minimal("str with spaces", "str with spaces");
+        assert_minimal(
+            "=?iso-8859-1?Q?J=FCrgen_M=FCller?= <j@x>",
+            "Jürgen Müller <j@x>",
+        );
+        assert_minimal(
+            "Re: Budget =?iso-8859-1?B?Zvxy?= 2026",
+            "Re: Budget für 2026",
+        );
+        assert_minimal("=?utf-8?B?5pel5pys6Kqe?= text", "日本語 text");
+        assert_minimal("a =?us-ascii?B?PT94Pz0=?= b", "a =?x?= b");
+    }
+
+    #[test]
+    fn minimal_whitespace() {
+        assert_minimal(" =?iso-8859-1?B?6XTp?=", " été");
+        assert_minimal("=?iso-8859-1?B?6XTp?= ", "été ");
+        assert_minimal("a  =?iso-8859-1?B?6Qnp?=\tb ", "a  é\té\tb ");
+    }
+
+    #[test]
+    fn minimal_charset() {
+        assert_minimal("=?utf-8?B?woA=?=", "\u{80}");
+        assert_minimal("=?utf-8?B?w6nigqw=?=", "é€");
+    }
+
+    #[test]
+    fn minimal_long() {
+        assert_minimal(&"word ".repeat(40), &"word ".repeat(40));
+        let decoded_str = "Re: Budget für 2026 ".repeat(10);
+        let encoded_str = Encoder::new().minimal(true).encode(&decoded_str);
+        assert_eq!(decode(encoded_str.as_bytes()).unwrap(), decoded_str);
+
+        let folded_str = Encoder::new()
+            .minimal(true)
+            .encode_header("Subject", &"x".repeat(100));
+        assert!(folded_str.starts_with("=?us-ascii?"));
+        assert_eq!(decode(folded_str.as_bytes()).unwrap(), "x".repeat(100));
+
+        let decoded_str = format!("{}then Jürgen and more", "word ".repeat(13));
+        let folded_str = Encoder::new()
+            .minimal(true)
+            .encode_header("Subject", &decoded_str);
+        assert!(folded_str.contains("word\r\n then"), "{:?}", folded_str);
+        assert_eq!(decode(folded_str.as_bytes()).unwrap(), decoded_str);
+    }
+
+    fn assert_context(context: Context, decoded_str: &str) {
+        let encoder = Encoder::new().context(context).minimal(true);
+        let encoded_str = encoder.encode(decoded_str);
+        assert_eq!(decode(encoded_str.as_bytes()).unwrap(), decoded_str);
+
+        for word in encoded_str.split(' ').filter(|w| w.starts_with("=?")) {
+            assert!(is_legal_encoded_word(word.as_bytes(), context), "{}", word);
+        }
+    }
+
+    #[test]
+    fn context() {
+        let encoder = Encoder::new();
+        assert_eq!("=?utf-8?Q?a(b)?=", encoder.encode("a(b)"));
+        let encoder = encoder.context(Context::Comment);
+        assert_eq!("=?utf-8?Q?a=28b=29?=", encoder.encode("a(b)"));
+        let encoder = encoder.context(Context::Phrase);
+        assert_eq!("=?utf-8?Q?a=28b=29?=", encoder.encode("a(b)"));
+        assert_eq!("=?utf-8?B?YS5i?=", encoder.encode("a.b"));
+
+        for context in [Context::Text, Context::Comment, Context::Phrase] {
+            assert_context(context, "Jürgen (Müller) \"Jü\" <j@x>");
+            assert_context(context, "a.b?c=d_e (f) \\g");
+        }
+    }
+
+    #[test]
+    fn context_minimal() {
+        let encoder = Encoder::new().minimal(true).context(Context::Phrase);
+        assert_eq!("John Q. Public", encoder.encode("John Q. Public"));
+        assert_eq!(
+            "=?iso-8859-1?B?SvxyZ2VuIE38bGxlciA8akB4Pg==?=",
+            encoder.encode("Jürgen Müller <j@x>")
+        );
+
+        let encoder = Encoder::new().minimal(true).context(Context::Comment);
+        assert_eq!("\"quoted\"", encoder.encode("\"quoted\""));
+        assert_eq!("=?us-ascii?Q?=28nested=29?=", encoder.encode("(nested)"));
+    }
+
+    #[test]
+    fn encode_with_encoding() {
+        let decoded_bytes = "str with special çhàrß_=?".as_bytes();
+
+        for encoding in [Encoding::Q, Encoding::B] {
+            let encoded_text =
+                encoder::encode_with_encoding(encoding, Context::Text, decoded_bytes);
+            assert_eq!(
+                evaluator::decode_with_encoding(encoding, encoded_text.as_bytes()).unwrap(),
+                decoded_bytes
+            );
+        }
+    }
+
+    #[test]
+    fn legacy_params() {
+        let params = Encoder::new()
+            .legacy_params(true)
+            .encode_param("filename", "日本.pdf");
+        assert_eq!(
+            params,
+            vec![
+                "filename=\"=?utf-8?B?5pel5pysLnBkZg==?=\"",
+                "filename*=utf-8''%E6%97%A5%E6%9C%AC.pdf",
+            ]
+        );
+
+        let params = Encoder::new()
+            .legacy_params(true)
+            .encode_param("filename", "a.pdf");
+        assert_eq!(params, vec!["filename=a.pdf"]);
+
+        let value = "日本語のファイル名".repeat(5);
+        let params = Encoder::new()
+            .legacy_params(true)
+            .encode_param("filename", &value);
+        let legacy_value = params[0]
+            .strip_prefix("filename=\"")
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap();
+        assert_eq!(decode(legacy_value.as_bytes()).unwrap(), value);
+        for line in format!(" {};", params.join(";\r\n ")).split("\r\n") {
+            assert!(line.len() <= 76, "line too long: {}", line);
+        }
+    }
+}
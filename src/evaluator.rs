@@ -1,103 +1,956 @@
-use charset::{self, Charset};
+use std::ops::Range;
 
-use crate::parser::{Ast, Node::*};
-use log::warn;
+use crate::ast::{ClearText, Encoding};
+use crate::b_encoding;
+use crate::detection::{self, Confidence, DetectedCharset};
+use crate::mojibake::{self, RepairedMojibake};
+use crate::parser::{Ast, EncodedBytes, Node, Node::*};
+use crate::q_encoding::{self, QRepair};
+use crate::registry::ResolvedCharset;
+use crate::substitution::{self, SubstitutedCharset};
+use crate::{Decoded, Decoder, Diagnostic, DiagnosticKind, ErrorPolicy, Fallback, Location};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
-    #[error(transparent)]
-    DecodeUtf8Error(#[from] std::str::Utf8Error),
     #[error(transparent)]
     DecodeBase64Error(#[from] base64::DecodeError),
-    #[error(transparent)]
-    DecodeQuotedPrintableError(#[from] quoted_printable::QuotedPrintableError),
+    #[error("the Q encoded text is malformed: {0:?}")]
+    DecodeQError(QRepair),
+    #[error("the encoded text of the encoded word cannot be decoded {0}")]
+    UndecodableEncodedWordError(Location),
+    #[error("the clear text is not valid UTF-8 {0}")]
+    InvalidClearTextError(Location),
 }
 
-fn decode_utf8(encoded_bytes: &Vec<u8>) -> Result<&str> {
-    Ok(std::str::from_utf8(&encoded_bytes)?)
-}
-
-fn decode_base64(encoded_bytes: &Vec<u8>) -> Result<Vec<u8>> {
-    let decoded_bytes = base64::decode(&encoded_bytes)?;
+fn decode_base64(encoded_bytes: &[u8]) -> Result<Vec<u8>> {
+    let decoded_bytes = base64::decode(encoded_bytes)?;
     Ok(decoded_bytes)
 }
 
-fn decode_quoted_printable(encoded_bytes: &Vec<u8>) -> Result<Vec<u8>> {
-    let parse_mode = quoted_printable::ParseMode::Robust;
+fn decode_q(encoded_bytes: &[u8]) -> Result<Vec<u8>> {
+    match q_encoding::decode(encoded_bytes) {
+        (decoded_bytes, repairs) if repairs.is_empty() => Ok(decoded_bytes),
+        (_, repairs) => Err(Error::DecodeQError(repairs[0])),
+    }
+}
 
-    const SPACE: u8 = ' ' as u8;
-    const UNDERSCORE: u8 = '_' as u8;
+pub fn decode_with_encoding(encoding: Encoding, encoded_bytes: &[u8]) -> Result<Vec<u8>> {
+    match encoding {
+        Encoding::B => decode_base64(encoded_bytes),
+        Encoding::Q => decode_q(encoded_bytes),
+    }
+}
 
-    let encoded_bytes = encoded_bytes
-        .iter()
-        .map(|b| if *b == UNDERSCORE { SPACE } else { *b })
-        .collect::<Vec<_>>();
+/// Decode the encoded text of the given encoded word, repairing it
+/// unless the decoder is strict. Returns `None` when it cannot be
+/// decoded.
+fn decode_encoded_text(
+    node: &EncodedBytes,
+    decoder: &Decoder,
+) -> Option<(Vec<u8>, Vec<DiagnosticKind>)> {
+    let lenient = !decoder.strict && !decoder.strict_encoded_text;
 
-    let decoded_bytes = quoted_printable::decode(encoded_bytes, parse_mode)?;
+    match node.encoding? {
+        Encoding::B if lenient => {
+            let (decoded_bytes, repairs) = b_encoding::decode_lenient(&node.bytes)?;
+            let repairs = repairs.into_iter().map(DiagnosticKind::RepairedBase64);
+            Some((decoded_bytes, repairs.collect()))
+        }
+        Encoding::Q if lenient => {
+            let (decoded_bytes, repairs) = q_encoding::decode(&node.bytes);
+            let repairs = repairs.into_iter().map(DiagnosticKind::RepairedQ);
+            Some((decoded_bytes, repairs.collect()))
+        }
+        encoding => {
+            let decoded_bytes = decode_with_encoding(encoding, &node.bytes).ok()?;
+            Some((decoded_bytes, vec![]))
+        }
+    }
+}
 
-    Ok(decoded_bytes)
+pub fn decode_with_charset(decoder: &Decoder, charset: &[u8], decoded_bytes: &[u8]) -> String {
+    let resolved = decoder.charsets.resolve(charset);
+    choose_charset(decoder, charset, resolved, decoded_bytes).decoded_str
 }
 
-pub fn decode_with_encoding(
-    encoding: char,
-    encoded_bytes: &Vec<u8>,
-) -> Result<Vec<u8>> {
-    match encoding.to_uppercase().next() {
-        Some('B') => decode_base64(encoded_bytes),
-        Some('Q') | _ => decode_quoted_printable(encoded_bytes),
+/// Decode the given bytes with the given resolved charset, telling what
+/// went wrong if anything.
+fn decode_charset(
+    resolved: Option<&ResolvedCharset>,
+    charset: &[u8],
+    decoded_bytes: &[u8],
+) -> (String, Option<(DiagnosticKind, Fallback)>) {
+    let label = || String::from_utf8_lossy(charset).to_string();
+
+    match resolved {
+        Some(resolved) => {
+            let (decoded_str, had_errors) = resolved.decode(decoded_bytes);
+            let diagnostic = if had_errors {
+                Some((
+                    DiagnosticKind::InvalidCharsetBytes(label()),
+                    Fallback::ReplacementChar,
+                ))
+            } else {
+                None
+            };
+            (decoded_str, diagnostic)
+        }
+        None => {
+            let decoded_str = charset::decode_ascii(decoded_bytes).into_owned();
+            (
+                decoded_str,
+                Some((DiagnosticKind::UnknownCharset(label()), Fallback::Ascii)),
+            )
+        }
     }
 }
 
-pub fn decode_with_charset(
-    charset: &Vec<u8>,
-    decoded_bytes: &Vec<u8>,
-) -> Result<String> {
-    let decoded_str = match Charset::for_label(charset) {
-        Some(charset) => charset.decode(decoded_bytes).0,
-        None => charset::decode_ascii(decoded_bytes),
+/// Charset chosen to decode the bytes of encoded words, with how it
+/// was chosen.
+struct CharsetChoice {
+    resolved: Option<ResolvedCharset>,
+    decoded_str: String,
+    diagnostic: Option<(DiagnosticKind, Fallback)>,
+    confidence: Option<Confidence>,
+}
+
+/// Decode the given bytes with the given charset, falling back on
+/// charset detection for unknown charsets, or on the fallback chain of
+/// the decoder for invalid or implausible bytes.
+fn choose_charset(
+    decoder: &Decoder,
+    charset: &[u8],
+    resolved: Option<ResolvedCharset>,
+    decoded_bytes: &[u8],
+) -> CharsetChoice {
+    let label = || String::from_utf8_lossy(charset).to_string();
+
+    if resolved.is_none() && decoder.detect_charsets {
+        let tld = decoder.detection_tld.as_deref();
+        let (resolved, confidence) = detection::detect(decoded_bytes, tld);
+        let (decoded_str, _) = resolved.decode(decoded_bytes);
+        return CharsetChoice {
+            resolved: Some(resolved),
+            decoded_str,
+            diagnostic: Some((DiagnosticKind::UnknownCharset(label()), Fallback::Detected)),
+            confidence: Some(confidence),
+        };
+    }
+
+    let (decoded_str, diagnostic) = decode_charset(resolved.as_ref(), charset, decoded_bytes);
+    let kind = match (&diagnostic, &resolved) {
+        _ if decoder.charset_fallbacks.is_empty() => None,
+        (Some((kind, _)), _) => Some(kind.clone()),
+        (None, Some(resolved))
+            if !substitution::is_plausible(resolved, decoded_bytes, &decoded_str) =>
+        {
+            Some(DiagnosticKind::ImplausibleCharsetBytes(label()))
+        }
+        (None, _) => None,
     };
 
-    Ok(decoded_str.into_owned())
+    match kind.and_then(|kind| Some((kind, substitution::substitute(decoder, decoded_bytes)?))) {
+        Some((kind, (resolved, decoded_str))) => CharsetChoice {
+            resolved: Some(resolved),
+            decoded_str,
+            diagnostic: Some((kind, Fallback::NextCharset)),
+            confidence: None,
+        },
+        None => CharsetChoice {
+            resolved,
+            decoded_str,
+            diagnostic,
+            confidence: None,
+        },
+    }
+}
+
+/// Find the length of the longest common prefix of two strings, on a
+/// char boundary.
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or_else(|| a.len().min(b.len()), |((i, _), _)| i)
 }
 
-pub fn run(ast: &Ast) -> Result<String> {
-    let mut output = String::new();
+/// Decoded text of a node, with the canonical name of the charset it
+/// was decoded with, if it is an encoded word of a known charset.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Evaluated {
+    pub text: String,
+    pub charset: Option<String>,
+    /// Whitespace separating the node from the previous encoded word,
+    /// kept when either of them is kept verbatim.
+    pub separator: Option<ClearText>,
+}
 
-    for node in ast {
+impl From<String> for Evaluated {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            charset: None,
+            separator: None,
+        }
+    }
+}
+
+/// Decoded bytes of consecutive encoded words sharing the same charset,
+/// which need to be decoded together in case a character is split
+/// across words.
+struct Pending<'a> {
+    charset: &'a [u8],
+    resolved: Option<ResolvedCharset>,
+    decoded_bytes: Vec<u8>,
+    ends: Vec<usize>,
+    span: Range<usize>,
+}
+
+impl Pending<'_> {
+    /// Tell whether the given encoded word shares the charset of the
+    /// pending words.
+    fn shares_charset(&self, charset: &[u8], resolved: &Option<ResolvedCharset>) -> bool {
+        match (&self.resolved, resolved) {
+            (Some(a), Some(b)) => a.name() == b.name(),
+            (None, None) => self.charset.eq_ignore_ascii_case(charset),
+            _ => false,
+        }
+    }
+
+    /// Decode the pending words, and give each of them the characters
+    /// that are fully decoded once its bytes are known.
+    fn flush(self, decoder: &Decoder, texts: &mut Vec<Evaluated>, decoded: &mut Decoded) {
+        let choice = choose_charset(decoder, self.charset, self.resolved, &self.decoded_bytes);
+        let resolved = choice.resolved.as_ref();
+        let decoded_str = choice.decoded_str;
+
+        if let Some(resolved) = resolved {
+            let charset = self.charset;
+            let label = || String::from_utf8_lossy(charset).to_string();
+            if let Some(confidence) = choice.confidence {
+                decoded.detected_charsets.push(DetectedCharset {
+                    label: label(),
+                    charset: resolved.name().to_string(),
+                    confidence,
+                    span: self.span.clone(),
+                });
+            }
+            if let Some((_, Fallback::NextCharset)) = choice.diagnostic {
+                decoded.substituted_charsets.push(SubstitutedCharset {
+                    label: label(),
+                    charset: resolved.name().to_string(),
+                    span: self.span.clone(),
+                });
+            }
+        }
+        if let Some((kind, fallback)) = choice.diagnostic {
+            decoded.diagnostics.push(Diagnostic {
+                kind,
+                span: self.span,
+                fallback,
+            });
+        }
+
+        let charset = resolved.map(|resolved| resolved.name().to_string());
+        let evaluated = |text: &str| Evaluated {
+            text: text.to_string(),
+            charset: charset.clone(),
+            separator: None,
+        };
+
+        let mut start = 0;
+        for end in &self.ends[..self.ends.len() - 1] {
+            let (prefix, _) = decode_charset(resolved, self.charset, &self.decoded_bytes[..*end]);
+            let prefix_len = common_prefix_len(&prefix, &decoded_str);
+            let end = prefix_len.max(start);
+            texts.push(evaluated(&decoded_str[start..end]));
+            start = end;
+        }
+        texts.push(evaluated(&decoded_str[start..]));
+    }
+}
+
+/// Apply the error policy to an encoded word whose encoded text cannot
+/// be decoded.
+fn fallback_encoded_word(
+    encoded_word: &[u8],
+    span: &Range<usize>,
+    error_policy: ErrorPolicy,
+) -> Result<(String, Fallback)> {
+    match error_policy {
+        ErrorPolicy::Verbatim => {
+            let encoded_word = String::from_utf8_lossy(encoded_word).to_string();
+            Ok((encoded_word, Fallback::EncodedWord))
+        }
+        ErrorPolicy::Replace => Ok(("\u{FFFD}".to_string(), Fallback::ReplacementChar)),
+        ErrorPolicy::Drop => Ok((String::new(), Fallback::Dropped)),
+        ErrorPolicy::Fail => {
+            let location = Location::new(span.start, span.start, encoded_word);
+            Err(Error::UndecodableEncodedWordError(location))
+        }
+    }
+}
+
+/// Unfold clear text, removing the CRLF of folding whitespace, as
+/// defined in RFC 5322 section 2.2.3. A bare LF, as left by tools
+/// converting line endings, is unfolded the same way.
+fn unfold(clear_str: &str) -> String {
+    let mut unfolded = String::with_capacity(clear_str.len());
+    let mut rest = clear_str;
+
+    while let Some(i) = rest.find('\n') {
+        let (line, next) = rest.split_at(i + 1);
+        rest = next;
+        if rest.starts_with([' ', '\t']) {
+            let line = &line[..i];
+            unfolded.push_str(line.strip_suffix('\r').unwrap_or(line));
+        } else {
+            unfolded.push_str(line);
+        }
+    }
+    unfolded.push_str(rest);
+
+    unfolded
+}
+
+/// Decode clear text that is not valid UTF-8 with the charset hint of
+/// the decoder, if any and if the bytes are valid in it.
+fn decode_hinted_clear_text(decoder: &Decoder, clear_bytes: &[u8]) -> Option<String> {
+    let hint = decoder.clear_text_charset.as_ref()?;
+    match decoder
+        .charsets
+        .resolve(hint.as_bytes())?
+        .decode(clear_bytes)
+    {
+        (clear_str, false) => Some(clear_str),
+        (_, true) => None,
+    }
+}
+
+/// Apply the error policy to clear text that is not valid UTF-8.
+fn fallback_clear_text(
+    clear_bytes: &[u8],
+    span: &Range<usize>,
+    error_policy: ErrorPolicy,
+    err: std::str::Utf8Error,
+) -> Result<(String, Fallback)> {
+    match error_policy {
+        ErrorPolicy::Verbatim | ErrorPolicy::Replace => {
+            let clear_str = String::from_utf8_lossy(clear_bytes).to_string();
+            Ok((clear_str, Fallback::ReplacementChar))
+        }
+        ErrorPolicy::Drop => {
+            let clear_str = clear_bytes
+                .utf8_chunks()
+                .map(|chunk| chunk.valid())
+                .collect();
+            Ok((clear_str, Fallback::Dropped))
+        }
+        ErrorPolicy::Fail => {
+            let offset = span.start + err.valid_up_to();
+            let location = Location::new(offset, span.start, clear_bytes);
+            Err(Error::InvalidClearTextError(location))
+        }
+    }
+}
+
+/// Byte span of the given node in the header.
+fn node_span(node: &Node) -> Range<usize> {
+    match node {
+        EncodedBytes(node) => node.span.clone(),
+        ClearBytes(node) => node.span.clone(),
+    }
+}
+
+/// Repair the mojibake of each clear text, and of the joined text of
+/// each run of adjacent encoded words, since a character can be split
+/// across encoded words.
+fn repair_mojibake(ast: &Ast, texts: &mut [Evaluated], decoded: &mut Decoded) {
+    let mut start = 0;
+
+    while start < ast.len() {
+        let end = match ast[start] {
+            EncodedBytes(_) => ast[start..]
+                .iter()
+                .position(|node| matches!(node, ClearBytes(_)))
+                .map_or(ast.len(), |len| start + len),
+            ClearBytes(_) => start + 1,
+        };
+        let run = &mut texts[start..end];
+        let run_texts = run
+            .iter()
+            .map(|evaluated| evaluated.text.as_str())
+            .collect::<Vec<_>>();
+
+        if let Some((repaired, charset)) = mojibake::repair_joined(&run_texts) {
+            let span = node_span(&ast[start]).start..node_span(&ast[end - 1]).end;
+            decoded.diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::Mojibake,
+                span: span.clone(),
+                fallback: Fallback::Repaired,
+            });
+            decoded.repaired_mojibake.push(RepairedMojibake {
+                text: run_texts.concat(),
+                repaired: repaired.concat(),
+                charset: charset.to_string(),
+                span,
+            });
+            for (evaluated, repaired) in run.iter_mut().zip(repaired) {
+                evaluated.text = repaired;
+            }
+        }
+
+        start = end;
+    }
+}
+
+/// Decode the given AST of the given header into the decoded text of
+/// each of its nodes, collecting the problems met and the charsets
+/// detected along the way.
+pub fn evaluate(
+    ast: &Ast,
+    encoded_str: &[u8],
+    decoder: &Decoder,
+    decoded: &mut Decoded,
+) -> Result<Vec<Evaluated>> {
+    let mut texts = vec![];
+    let mut pending: Option<Pending> = None;
+    let mut verbatim = vec![false; ast.len()];
+
+    for (i, node) in ast.iter().enumerate() {
         match node {
-            EncodedBytes(node) => {
-                let decoded_str = match decode_with_encoding(node.encoding, &node.bytes) {
-                    Ok(decoded_bytes) => {
-                        match decode_with_charset(&node.charset, &decoded_bytes) {
-                            Ok(decodecd_str) => decodecd_str,
-                            Err(e) => {
-                                warn!("failed to decode bytes to charset {:?} : {:?}", &node.charset, e);
-                                String::from_utf8_lossy(&node.bytes).to_string()
+            EncodedBytes(node) => match decode_encoded_text(node, decoder) {
+                Some((decoded_bytes, repairs)) => {
+                    decoded
+                        .diagnostics
+                        .extend(repairs.into_iter().map(|kind| Diagnostic {
+                            kind,
+                            span: node.span.clone(),
+                            fallback: Fallback::Repaired,
+                        }));
+
+                    let resolved = decoder.charsets.resolve(&node.charset);
+                    match &mut pending {
+                        Some(pending) if pending.shares_charset(&node.charset, &resolved) => {
+                            pending.decoded_bytes.extend(decoded_bytes);
+                            pending.ends.push(pending.decoded_bytes.len());
+                            pending.span.end = node.span.end;
+                        }
+                        _ => {
+                            if let Some(pending) = pending.take() {
+                                pending.flush(decoder, &mut texts, decoded);
                             }
+                            pending = Some(Pending {
+                                charset: &node.charset,
+                                resolved,
+                                ends: vec![decoded_bytes.len()],
+                                decoded_bytes,
+                                span: node.span.clone(),
+                            });
                         }
-                    },
-                    Err(e) => {
-                        warn!("failed to decode bytes from {}: {:?}", node.encoding, e);
-                        String::from_utf8_lossy(&node.bytes).to_string()
                     }
-                };
-                output.push_str(&decoded_str);
-            }
-            ClearBytes(clear_bytes) => {
-                match decode_utf8(&clear_bytes) {
-                    Ok(clear_str) => {
-                        output.push_str(clear_str);
-                    },
+                }
+                None => {
+                    if let Some(pending) = pending.take() {
+                        pending.flush(decoder, &mut texts, decoded);
+                    }
+                    let encoded_word = &encoded_str[node.span.clone()];
+                    let (text, fallback) =
+                        fallback_encoded_word(encoded_word, &node.span, decoder.error_policy)?;
+                    verbatim[i] = fallback == Fallback::EncodedWord;
+                    let kind = match node.encoding {
+                        Some(Encoding::B) => DiagnosticKind::InvalidBase64,
+                        Some(Encoding::Q) => DiagnosticKind::InvalidQEscape,
+                        None => DiagnosticKind::UnknownEncoding(
+                            String::from_utf8_lossy(&node.encoding_label).to_string(),
+                        ),
+                    };
+                    decoded.diagnostics.push(Diagnostic {
+                        kind,
+                        span: node.span.clone(),
+                        fallback,
+                    });
+                    texts.push(Evaluated {
+                        text,
+                        charset: decoder
+                            .charsets
+                            .resolve(&node.charset)
+                            .map(|resolved| resolved.name().to_string()),
+                        separator: None,
+                    });
+                }
+            },
+            ClearBytes(node) => {
+                if let Some(pending) = pending.take() {
+                    pending.flush(decoder, &mut texts, decoded);
+                }
+                match std::str::from_utf8(&node.bytes) {
+                    Ok(clear_str) => texts.push(unfold(clear_str).into()),
                     Err(e) => {
-                        warn!("failed to decode clear bytes to utf-8: {:?}", e);
-                        output.push_str(&*String::from_utf8_lossy(&clear_bytes))
+                        let (text, fallback) = match decode_hinted_clear_text(decoder, &node.bytes)
+                        {
+                            Some(text) => {
+                                decoded.charset_hint_used = true;
+                                (text, Fallback::CharsetHint)
+                            }
+                            None => fallback_clear_text(
+                                &node.bytes,
+                                &node.span,
+                                decoder.error_policy,
+                                e,
+                            )?,
+                        };
+                        decoded.diagnostics.push(Diagnostic {
+                            kind: DiagnosticKind::InvalidUtf8,
+                            span: node.span.clone(),
+                            fallback,
+                        });
+                        texts.push(unfold(&text).into())
                     }
                 }
             }
         }
     }
 
-    Ok(output)
+    if let Some(pending) = pending {
+        pending.flush(decoder, &mut texts, decoded);
+    }
+
+    // The whitespace separating a verbatim encoded word from adjacent
+    // encoded words is kept, since the word is not decoded.
+    for i in 1..ast.len() {
+        if let (EncodedBytes(prev), EncodedBytes(node)) = (&ast[i - 1], &ast[i]) {
+            if verbatim[i - 1] || verbatim[i] {
+                let span = prev.span.end..node.span.start;
+                texts[i].separator = Some(ClearText {
+                    text: unfold(&String::from_utf8_lossy(&encoded_str[span.clone()])),
+                    span,
+                });
+            }
+        }
+    }
+
+    if decoder.repair_mojibake {
+        repair_mojibake(ast, &mut texts, decoded);
+    }
+
+    Ok(texts)
+}
+
+pub fn run(ast: &Ast, encoded_str: &[u8], decoder: &Decoder) -> Result<Decoded> {
+    let mut decoded = Decoded::default();
+    decoded.text = evaluate(ast, encoded_str, decoder, &mut decoded)?
+        .into_iter()
+        .flat_map(|evaluated| {
+            evaluated
+                .separator
+                .map(|separator| separator.text)
+                .into_iter()
+                .chain([evaluated.text])
+        })
+        .collect();
+    decoded
+        .diagnostics
+        .sort_by_key(|diagnostic| diagnostic.span.start);
+
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Base64Repair, Confidence, Decoder, DetectedCharset, Diagnostic, DiagnosticKind::*,
+        ErrorPolicy, Fallback, QRepair, RepairedMojibake, SubstitutedCharset,
+    };
+
+    fn assert_diagnostics(encoded_str: &[u8], text: &str, diagnostics: &[Diagnostic]) {
+        let decoded = Decoder::new().decode_with_diagnostics(encoded_str).unwrap();
+        assert_eq!(decoded.text, text);
+        assert_eq!(decoded.diagnostics, diagnostics);
+    }
+
+    #[test]
+    fn clean() {
+        assert_diagnostics(b"a =?utf-8?Q?=C3?= =?UTF-8?B?qQ==?= b", "a é b", &[]);
+    }
+
+    #[test]
+    fn charset_diagnostics() {
+        assert_diagnostics(
+            b"=?x-unknown?Q?a=E9?=",
+            "a\u{FFFD}",
+            &[Diagnostic {
+                kind: UnknownCharset("x-unknown".to_string()),
+                span: 0..20,
+                fallback: Fallback::Ascii,
+            }],
+        );
+        assert_diagnostics(
+            b"a =?utf-8?B?ww==?= =?utf-8?Q?=C3?=",
+            "a \u{FFFD}\u{FFFD}",
+            &[Diagnostic {
+                kind: InvalidCharsetBytes("utf-8".to_string()),
+                span: 2..34,
+                fallback: Fallback::ReplacementChar,
+            }],
+        );
+    }
+
+    #[test]
+    fn detected_charsets() {
+        let decoder = Decoder::new().detect_charsets(true);
+        let decoded = decoder
+            .decode_with_diagnostics(
+                b"a =?x-unknown?B?z/Do4uXyLA==?= =?X-UNKNOWN?Q?_=EA=E0=EA_=E4=E5=EB=E0=3F?=",
+            )
+            .unwrap();
+
+        assert_eq!(decoded.text, "a Привет, как дела?");
+        assert_eq!(
+            decoded.diagnostics,
+            vec![Diagnostic {
+                kind: UnknownCharset("x-unknown".to_string()),
+                span: 2..73,
+                fallback: Fallback::Detected,
+            }]
+        );
+        assert_eq!(
+            decoded.detected_charsets,
+            vec![DetectedCharset {
+                label: "x-unknown".to_string(),
+                charset: "windows-1251".to_string(),
+                confidence: Confidence::High,
+                span: 2..73,
+            }]
+        );
+
+        let decoded = decoder
+            .decode_with_diagnostics(b"=?utf-8?Q?caf=C3=A9?=")
+            .unwrap();
+        assert_eq!(decoded.detected_charsets, vec![]);
+    }
+
+    #[test]
+    fn substituted_charsets() {
+        let decoder = Decoder::new().charset_fallbacks(&["utf-8", "windows-1252"]);
+        let decoded = decoder
+            .decode_with_diagnostics(
+                b"=?iso-8859-1?Q?caf=C3=A9?= =?utf-8?Q?_cr=E8me?= =?iso-8859-1?Q?_br=FBl=E9e?=",
+            )
+            .unwrap();
+
+        assert_eq!(decoded.text, "café crème brûlée");
+        assert_eq!(
+            decoded.diagnostics,
+            vec![
+                Diagnostic {
+                    kind: ImplausibleCharsetBytes("iso-8859-1".to_string()),
+                    span: 0..26,
+                    fallback: Fallback::NextCharset,
+                },
+                Diagnostic {
+                    kind: InvalidCharsetBytes("utf-8".to_string()),
+                    span: 27..47,
+                    fallback: Fallback::NextCharset,
+                },
+            ]
+        );
+        assert_eq!(
+            decoded.substituted_charsets,
+            vec![
+                SubstitutedCharset {
+                    label: "iso-8859-1".to_string(),
+                    charset: "UTF-8".to_string(),
+                    span: 0..26,
+                },
+                SubstitutedCharset {
+                    label: "utf-8".to_string(),
+                    charset: "windows-1252".to_string(),
+                    span: 27..47,
+                },
+            ]
+        );
+
+        let decoded = decoder
+            .decode_with_diagnostics(b"=?x-unknown?Q?caf=E9?=")
+            .unwrap();
+        assert_eq!(decoded.text, "café");
+        assert_eq!(
+            decoded.diagnostics[0].kind,
+            UnknownCharset("x-unknown".to_string())
+        );
+        assert_eq!(decoded.substituted_charsets[0].charset, "windows-1252");
+    }
+
+    #[test]
+    fn encoding_diagnostics() {
+        assert_diagnostics(
+            b"a =?utf-8?B?w6*k?= b",
+            "a =?utf-8?B?w6*k?= b",
+            &[Diagnostic {
+                kind: InvalidBase64,
+                span: 2..18,
+                fallback: Fallback::EncodedWord,
+            }],
+        );
+    }
+
+    #[test]
+    fn repaired_base64() {
+        assert_diagnostics(
+            b"=?utf-8?B?w6k?= =?utf-8?B?w6\r\n k=*?=",
+            "éé",
+            &[
+                Diagnostic {
+                    kind: RepairedBase64(Base64Repair::MissingPadding),
+                    span: 0..15,
+                    fallback: Fallback::Repaired,
+                },
+                Diagnostic {
+                    kind: RepairedBase64(Base64Repair::Whitespace),
+                    span: 16..36,
+                    fallback: Fallback::Repaired,
+                },
+                Diagnostic {
+                    kind: RepairedBase64(Base64Repair::TrailingGarbage),
+                    span: 16..36,
+                    fallback: Fallback::Repaired,
+                },
+            ],
+        );
+
+        let decoder = Decoder::new().strict_encoded_text(true);
+        assert_eq!(
+            decoder.decode(b"=?utf-8?B?w6\r\n k=*?=").unwrap(),
+            "=?utf-8?B?w6\r\n k=*?="
+        );
+    }
+
+    #[test]
+    fn repaired_q() {
+        assert_diagnostics(
+            b"=?iso-8859-1?q?caf=e9 cr=E8me?=",
+            "café crème",
+            &[
+                Diagnostic {
+                    kind: RepairedQ(QRepair::LowercaseEscape),
+                    span: 0..31,
+                    fallback: Fallback::Repaired,
+                },
+                Diagnostic {
+                    kind: RepairedQ(QRepair::LiteralWhitespace),
+                    span: 0..31,
+                    fallback: Fallback::Repaired,
+                },
+            ],
+        );
+
+        let decoded = Decoder::new()
+            .strict_encoded_text(true)
+            .decode_with_diagnostics(b"=?iso-8859-1?q?caf=e9?=")
+            .unwrap();
+        assert_eq!(decoded.text, "=?iso-8859-1?q?caf=e9?=");
+        assert_eq!(decoded.diagnostics[0].kind, InvalidQEscape);
+
+        let decoded = Decoder::new()
+            .strict(true)
+            .decode_with_diagnostics(b"=?iso-8859-1?q?caf=e9?=")
+            .unwrap();
+        assert_eq!(decoded.text, "=?iso-8859-1?q?caf=e9?=");
+        assert_eq!(decoded.diagnostics[0].kind, InvalidQEscape);
+    }
+
+    #[test]
+    fn unfolded_clear_text() {
+        assert_diagnostics(b"a\r\n b =?utf-8?Q?c?=\r\n\td", "a b c\td", &[]);
+        assert_diagnostics(b"a\r\nb\r\n", "a\r\nb\r\n", &[]);
+        assert_diagnostics(b"a\n b =?utf-8?Q?c?=\n\td", "a b c\td", &[]);
+        assert_diagnostics(b"a\nb\n", "a\nb\n", &[]);
+    }
+
+    #[test]
+    fn clear_text_diagnostics() {
+        assert_diagnostics(
+            b"caf\xE9 =?utf-8?Q?a?=",
+            "caf\u{FFFD} a",
+            &[Diagnostic {
+                kind: InvalidUtf8,
+                span: 0..5,
+                fallback: Fallback::ReplacementChar,
+            }],
+        );
+    }
+
+    #[test]
+    fn charset_hint() {
+        let decoder = Decoder::new().clear_text_charset("iso-8859-1");
+        let decoded = decoder
+            .decode_with_diagnostics(b"caf\xE9 =?utf-8?Q?cr=C3=A8me?= br\xFBl\xE9e")
+            .unwrap();
+
+        assert_eq!(decoded.text, "café crème brûlée");
+        assert!(decoded.charset_hint_used);
+        assert_eq!(
+            decoded.diagnostics,
+            vec![
+                Diagnostic {
+                    kind: InvalidUtf8,
+                    span: 0..5,
+                    fallback: Fallback::CharsetHint,
+                },
+                Diagnostic {
+                    kind: InvalidUtf8,
+                    span: 27..34,
+                    fallback: Fallback::CharsetHint,
+                },
+            ]
+        );
+
+        let decoded = decoder
+            .decode_with_diagnostics("café =?utf-8?Q?a?=".as_bytes())
+            .unwrap();
+        assert_eq!(decoded.text, "café a");
+        assert!(!decoded.charset_hint_used);
+
+        let decoded = Decoder::new()
+            .clear_text_charset("shift_jis")
+            .decode_with_diagnostics(b"\x82\xa0 \x82")
+            .unwrap();
+        assert_eq!(decoded.text, "\u{FFFD}\u{FFFD} \u{FFFD}");
+        assert!(!decoded.charset_hint_used);
+        assert_eq!(decoded.diagnostics[0].fallback, Fallback::ReplacementChar);
+    }
+
+    #[test]
+    fn repaired_mojibake() {
+        let encoded_str = b"=?utf-8?Q?Caf=C3=83=C2=A9?= caf\xC3\x83\xC2\xA9 =?utf-8?Q?caf=C3=A9?=";
+        let decoded = Decoder::new()
+            .repair_mojibake(true)
+            .decode_with_diagnostics(encoded_str)
+            .unwrap();
+
+        assert_eq!(decoded.text, "Café café café");
+        assert_eq!(
+            decoded.diagnostics,
+            vec![
+                Diagnostic {
+                    kind: Mojibake,
+                    span: 0..27,
+                    fallback: Fallback::Repaired,
+                },
+                Diagnostic {
+                    kind: Mojibake,
+                    span: 27..36,
+                    fallback: Fallback::Repaired,
+                },
+            ]
+        );
+        assert_eq!(
+            decoded.repaired_mojibake[0],
+            RepairedMojibake {
+                text: "CafÃ©".to_string(),
+                repaired: "Café".to_string(),
+                charset: "ISO-8859-1".to_string(),
+                span: 0..27,
+            }
+        );
+        assert_eq!(decoded.repaired_mojibake[1].text, " cafÃ© ");
+
+        assert_eq!(
+            Decoder::new().decode(encoded_str).unwrap(),
+            "CafÃ© cafÃ© café"
+        );
+    }
+
+    #[test]
+    fn repaired_mojibake_across_words() {
+        let encoded_str = b"=?utf-8?Q?Caf=C3=83?= =?utf-8?Q?=C2=A9?= =?iso-8859-1?Q?_ok?=";
+        let decoder = Decoder::new().repair_mojibake(true);
+        let decoded = decoder.decode_with_diagnostics(encoded_str).unwrap();
+
+        assert_eq!(decoded.text, "Café ok");
+        assert_eq!(
+            decoded.repaired_mojibake,
+            vec![RepairedMojibake {
+                text: "CafÃ© ok".to_string(),
+                repaired: "Café ok".to_string(),
+                charset: "ISO-8859-1".to_string(),
+                span: 0..61,
+            }]
+        );
+
+        let encoded_str = b"=?utf-8?Q?=C3=82?= =?utf-8?Q?=C2=A0?=";
+        assert_eq!(decoder.decode(encoded_str).unwrap(), "Â\u{A0}");
+    }
+
+    fn decode_with_policy(encoded_str: &[u8], error_policy: ErrorPolicy) -> (String, Fallback) {
+        let decoded = Decoder::new()
+            .error_policy(error_policy)
+            .decode_with_diagnostics(encoded_str)
+            .unwrap();
+        (decoded.text, decoded.diagnostics[0].fallback)
+    }
+
+    #[test]
+    fn error_policy_encoded_words() {
+        let encoded_str = b"a =?utf-8?B?w6*k?= =?utf-8?Q?b?=";
+
+        assert_eq!(
+            decode_with_policy(encoded_str, ErrorPolicy::Verbatim),
+            ("a =?utf-8?B?w6*k?= b".to_string(), Fallback::EncodedWord)
+        );
+        assert_eq!(
+            decode_with_policy(
+                b"=?utf-8?Q?a?=\r\n =?utf-8?B?w6*k?=  =?utf-8?B?w6*k?=",
+                ErrorPolicy::Verbatim
+            ),
+            (
+                "a =?utf-8?B?w6*k?=  =?utf-8?B?w6*k?=".to_string(),
+                Fallback::EncodedWord
+            )
+        );
+        assert_eq!(
+            decode_with_policy(encoded_str, ErrorPolicy::Replace),
+            ("a \u{FFFD}b".to_string(), Fallback::ReplacementChar)
+        );
+        assert_eq!(
+            decode_with_policy(encoded_str, ErrorPolicy::Drop),
+            ("a b".to_string(), Fallback::Dropped)
+        );
+
+        let err = Decoder::new()
+            .error_policy(ErrorPolicy::Fail)
+            .decode(encoded_str)
+            .unwrap_err();
+        let location = err.location().unwrap();
+        assert_eq!(location.offset, 2);
+        assert_eq!(location.encoded_word, "=?utf-8?B?w6*k?=");
+    }
+
+    #[test]
+    fn error_policy_clear_text() {
+        let encoded_str = b"caf\xE9s =?utf-8?Q?a?=";
+
+        assert_eq!(
+            decode_with_policy(encoded_str, ErrorPolicy::Verbatim),
+            ("caf\u{FFFD}s a".to_string(), Fallback::ReplacementChar)
+        );
+        assert_eq!(
+            decode_with_policy(encoded_str, ErrorPolicy::Replace),
+            ("caf\u{FFFD}s a".to_string(), Fallback::ReplacementChar)
+        );
+        assert_eq!(
+            decode_with_policy(encoded_str, ErrorPolicy::Drop),
+            ("cafs a".to_string(), Fallback::Dropped)
+        );
+
+        let err = Decoder::new()
+            .error_policy(ErrorPolicy::Fail)
+            .decode(encoded_str)
+            .unwrap_err();
+        assert_eq!(err.location().unwrap().offset, 3);
+    }
 }
@@ -0,0 +1,262 @@
+use crate::{params, Decoder};
+
+/// Maximum length of a sanitised filename in bytes, as allowed by most
+/// filesystems.
+pub const MAX_FILENAME_LEN: usize = 255;
+
+/// Name given to filenames left empty by the sanitisation.
+const DEFAULT_FILENAME: &str = "unnamed";
+
+/// Extensions longer than this are not kept when truncating.
+const MAX_EXTENSION_LEN: usize = 16;
+
+/// Names reserved by Windows, whatever their extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Change made to a filename by the sanitisation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilenameChange {
+    /// Directories were removed, only the last path component is kept.
+    DirectoryRemoved,
+    /// Control characters, like NUL, CR or LF, were removed.
+    ControlCharRemoved,
+    /// Bidirectional formatting characters, like U+202E RIGHT-TO-LEFT
+    /// OVERRIDE, were removed.
+    BidiControlRemoved,
+    /// Characters forbidden by Windows, like `:` or `*`, were replaced
+    /// by `_`.
+    ReservedCharReplaced,
+    /// Leading dots and whitespace, or trailing dots and whitespace,
+    /// were removed.
+    Trimmed,
+    /// A name reserved by Windows, like `CON` or `NUL.txt`, was
+    /// prefixed by `_`.
+    ReservedNameEscaped,
+    /// The filename was truncated to [`MAX_FILENAME_LEN`] bytes.
+    Truncated,
+    /// The filename was empty, and was replaced by `unnamed`.
+    EmptyReplaced,
+}
+
+/// Sanitised filename of an attachment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filename {
+    /// Sanitised filename, safe to use as a file name on any filesystem.
+    pub name: String,
+    /// Decoded filename, as given by the sender.
+    pub raw: String,
+    /// Changes made to the decoded filename, in order of application.
+    pub changes: Vec<FilenameChange>,
+}
+
+fn is_bidi_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+fn is_reserved_char(c: char) -> bool {
+    matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*')
+}
+
+fn is_reserved_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved_name| stem.eq_ignore_ascii_case(reserved_name))
+}
+
+/// Find the largest char boundary of the given string not greater than
+/// the given length.
+fn floor_char_boundary(s: &str, mut len: usize) -> usize {
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    len
+}
+
+/// Truncate the given filename to [`MAX_FILENAME_LEN`] bytes, keeping
+/// its extension when it is short enough.
+fn truncate(name: &str) -> String {
+    let extension = match name.rfind('.') {
+        Some(i) if i > 0 && name.len() - i <= MAX_EXTENSION_LEN => &name[i..],
+        _ => "",
+    };
+    let stem = &name[..name.len() - extension.len()];
+    let stem_len = floor_char_boundary(stem, MAX_FILENAME_LEN - extension.len());
+
+    // Trailing dots are trimmed again, as for the whole name.
+    let stem = stem[..stem_len].trim_end_matches(|c: char| c == '.' || c.is_whitespace());
+
+    format!("{}{}", stem, extension)
+}
+
+/// Sanitise a decoded filename, so that it is safe to use as a file
+/// name on any filesystem.
+pub fn sanitize(raw: &str) -> Filename {
+    let mut changes = vec![];
+    let mut push_change = |change| {
+        if !changes.contains(&change) {
+            changes.push(change);
+        }
+    };
+
+    // Browsers used to send full paths, with either separator.
+    let name = match raw.rfind(['/', '\\']) {
+        Some(i) => {
+            push_change(FilenameChange::DirectoryRemoved);
+            &raw[i + 1..]
+        }
+        None => raw,
+    };
+
+    let mut sanitized_name = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_control() {
+            push_change(FilenameChange::ControlCharRemoved);
+        } else if is_bidi_control(c) {
+            push_change(FilenameChange::BidiControlRemoved);
+        } else if is_reserved_char(c) {
+            push_change(FilenameChange::ReservedCharReplaced);
+            sanitized_name.push('_');
+        } else {
+            sanitized_name.push(c);
+        }
+    }
+
+    // Leading dots hide files, and make `.` and `..` point to
+    // directories. Windows drops trailing dots and spaces.
+    let trimmed_name = sanitized_name
+        .trim_start_matches(|c: char| c == '.' || c.is_whitespace())
+        .trim_end_matches(|c: char| c == '.' || c.is_whitespace());
+    if trimmed_name.len() != sanitized_name.len() {
+        push_change(FilenameChange::Trimmed);
+    }
+    let mut sanitized_name = trimmed_name.to_string();
+
+    if is_reserved_name(&sanitized_name) {
+        push_change(FilenameChange::ReservedNameEscaped);
+        sanitized_name.insert(0, '_');
+    }
+
+    if sanitized_name.len() > MAX_FILENAME_LEN {
+        push_change(FilenameChange::Truncated);
+        sanitized_name = truncate(&sanitized_name);
+    }
+
+    if sanitized_name.is_empty() {
+        push_change(FilenameChange::EmptyReplaced);
+        sanitized_name = DEFAULT_FILENAME.to_string();
+    }
+
+    Filename {
+        name: sanitized_name,
+        raw: raw.to_string(),
+        changes,
+    }
+}
+
+/// Decode and sanitise the filename of a `Content-Disposition` header,
+/// or the name of a `Content-Type` header.
+///
+/// Encoded words are decoded even in quoted values, as most mail
+/// clients produce them for filenames.
+pub fn decode(header_value: &[u8], decoder: &Decoder) -> crate::Result<Option<Filename>> {
+    let decoder = decoder.clone().lenient_params(true);
+    let header = params::parse(header_value, &decoder)?;
+
+    Ok(header
+        .param("filename")
+        .or_else(|| header.param("name"))
+        .map(sanitize))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        filename::{self, FilenameChange::*, MAX_FILENAME_LEN},
+        Decoder,
+    };
+
+    fn assert_sanitized(raw: &str, name: &str, changes: &[filename::FilenameChange]) {
+        let filename = filename::sanitize(raw);
+        assert_eq!(filename.raw, raw);
+        assert_eq!(filename.name, name);
+        assert_eq!(filename.changes, changes);
+    }
+
+    #[test]
+    fn sanitize() {
+        assert_sanitized("report.pdf", "report.pdf", &[]);
+        assert_sanitized("日本語 資料.pdf", "日本語 資料.pdf", &[]);
+        assert_sanitized("../../etc/passwd", "passwd", &[DirectoryRemoved]);
+        assert_sanitized(r"C:\Users\a\report.pdf", "report.pdf", &[DirectoryRemoved]);
+        assert_sanitized("a\0b\r\n.txt", "ab.txt", &[ControlCharRemoved]);
+        assert_sanitized(
+            "invoice\u{202E}fdp.exe",
+            "invoicefdp.exe",
+            &[BidiControlRemoved],
+        );
+        assert_sanitized("a:b*c?.txt", "a_b_c_.txt", &[ReservedCharReplaced]);
+        assert_sanitized(" .hidden. ", "hidden", &[Trimmed]);
+    }
+
+    #[test]
+    fn sanitize_reserved_names() {
+        assert_sanitized("CON", "_CON", &[ReservedNameEscaped]);
+        assert_sanitized("nul.txt", "_nul.txt", &[ReservedNameEscaped]);
+        assert_sanitized("com1.tar.gz", "_com1.tar.gz", &[ReservedNameEscaped]);
+        assert_sanitized("console.txt", "console.txt", &[]);
+    }
+
+    #[test]
+    fn sanitize_empty() {
+        assert_sanitized("", "unnamed", &[EmptyReplaced]);
+        assert_sanitized("..", "unnamed", &[Trimmed, EmptyReplaced]);
+        assert_sanitized("dir/", "unnamed", &[DirectoryRemoved, EmptyReplaced]);
+    }
+
+    #[test]
+    fn sanitize_truncate() {
+        let filename = filename::sanitize(&format!("{}.pdf", "é".repeat(200)));
+        assert_eq!(filename.name.len(), MAX_FILENAME_LEN - 1);
+        assert!(filename.name.ends_with("é.pdf"));
+        assert_eq!(filename.changes, &[Truncated]);
+
+        let filename = filename::sanitize(&format!("a.{}", "b".repeat(300)));
+        assert_eq!(filename.name.len(), MAX_FILENAME_LEN);
+        assert_eq!(filename.changes, &[Truncated]);
+
+        let filename = filename::sanitize(&format!("{}..{}", "x".repeat(254), "y".repeat(20)));
+        assert_eq!(filename.name, "x".repeat(254));
+        assert_eq!(filename.changes, &[Truncated]);
+
+        let filename = filename::sanitize(&format!("{}. .pdf", "x".repeat(250)));
+        assert_eq!(filename.name, format!("{}.pdf", "x".repeat(250)));
+    }
+
+    #[test]
+    fn decode() {
+        let decoder = Decoder::new();
+        let decode = |header_value: &[u8]| filename::decode(header_value, &decoder).unwrap();
+
+        let filename = decode(b"attachment; filename=\"=?utf-8?Q?..=2F=E6=97=A5.txt?=\"").unwrap();
+        assert_eq!(filename.raw, "../日.txt");
+        assert_eq!(filename.name, "日.txt");
+
+        let filename = decode(b"attachment; filename*=utf-8''%E6%97%A5%0A.txt").unwrap();
+        assert_eq!(filename.name, "日.txt");
+
+        let filename = decode(b"application/pdf; name=report.pdf").unwrap();
+        assert_eq!(filename.name, "report.pdf");
+
+        let filename = decode(b"attachment; filename*=utf-8''%zz; filename=\"ok.txt\"").unwrap();
+        assert_eq!(filename.name, "ok.txt");
+
+        assert_eq!(decode(b"inline"), None);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    evaluator, params, rfc2231, Decoder, Location, ParamDiagnostic, ParamDiagnosticKind,
+    ParamHeader,
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    #[error("the ext-value has no charset and language {0}")]
+    ParseExtValueError(Location),
+    #[error("the charset {0:?} is not allowed in ext-values {1}")]
+    UnsupportedCharsetError(String, Location),
+    #[error(transparent)]
+    DecodePercentError(#[from] rfc2231::Error),
+}
+
+/// Decoded RFC 8187 ext-value, like `UTF-8'en'na%C3%AFve.txt`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtValue {
+    /// Canonical name of the charset, `UTF-8` or `ISO-8859-1`.
+    pub charset: String,
+    /// Language tag, if any.
+    pub language: Option<String>,
+    /// Decoded value.
+    pub value: String,
+}
+
+/// Find the canonical name of the given charset, if allowed in
+/// ext-values. RFC 8187 only requires UTF-8, ISO-8859-1 is kept for
+/// compatibility with RFC 5987.
+fn canonical_charset(charset: &[u8]) -> Option<&'static str> {
+    if charset.eq_ignore_ascii_case(b"utf-8") {
+        Some("UTF-8")
+    } else if charset.eq_ignore_ascii_case(b"iso-8859-1") {
+        Some("ISO-8859-1")
+    } else {
+        None
+    }
+}
+
+pub fn decode_ext_value(ext_value: &[u8], decoder: &Decoder) -> Result<ExtValue> {
+    let (charset, language, value) = rfc2231::split_extended_value(ext_value)
+        .ok_or_else(|| Error::ParseExtValueError(Location::new(ext_value.len(), 0, ext_value)))?;
+
+    let charset = canonical_charset(charset).ok_or_else(|| {
+        let location = Location::new(0, 0, ext_value);
+        Error::UnsupportedCharsetError(String::from_utf8_lossy(charset).to_string(), location)
+    })?;
+    let language = match language {
+        [] => None,
+        language => Some(String::from_utf8_lossy(language).to_string()),
+    };
+    let decoded_bytes = rfc2231::decode_percent(ext_value, ext_value.len() - value.len())?;
+    let value = evaluator::decode_with_charset(decoder, charset.as_bytes(), &decoded_bytes);
+
+    Ok(ExtValue {
+        charset: charset.to_string(),
+        language,
+        value,
+    })
+}
+
+/// Parse and decode the value of a HTTP header with parameters, like
+/// `Content-Disposition`, as defined in RFC 8187 and RFC 6266.
+pub fn decode_params(header_value: &[u8], decoder: &Decoder) -> Result<ParamHeader> {
+    let (value, raw_params) = params::split(header_value);
+    let mut params: Vec<(String, String)> = vec![];
+    let mut extended_names: Vec<String> = vec![];
+    let mut diagnostics = vec![];
+
+    for (name, value) in raw_params {
+        let name = name.to_lowercase();
+
+        let (name, value) = match name.strip_suffix('*') {
+            // An ext-value that cannot be decoded is skipped, so that the
+            // regular value is used instead.
+            Some(name) => match decode_ext_value(&value, decoder) {
+                Ok(ext_value) => {
+                    extended_names.push(name.to_string());
+                    (name.to_string(), ext_value.value)
+                }
+                Err(_) => {
+                    diagnostics.push(ParamDiagnostic {
+                        name: name.to_string(),
+                        kind: ParamDiagnosticKind::InvalidExtendedValue,
+                    });
+                    continue;
+                }
+            },
+            // Ext-values are preferred over regular values.
+            None if extended_names.contains(&name) => continue,
+            // Regular values may contain RFC 2047 encoded words sent by
+            // legacy browsers.
+            None => {
+                let value =
+                    rfc2231::decode_clear_value(&name, &value, true, decoder, &mut diagnostics);
+                (name, value)
+            }
+        };
+
+        match params.iter_mut().find(|(n, _)| *n == name) {
+            Some(param) => param.1 = value,
+            None => params.push((name, value)),
+        }
+    }
+
+    Ok(ParamHeader {
+        value: String::from_utf8_lossy(value).to_string(),
+        params,
+        diagnostics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        http::{self, Error, ExtValue},
+        registry::tests::Upper,
+        rfc2231, CharsetRegistry, Decoder, ParamDiagnostic, ParamDiagnosticKind,
+    };
+
+    #[test]
+    fn ext_value() {
+        assert_eq!(
+            http::decode_ext_value(b"UTF-8''na%C3%AFve.txt", &Decoder::new()).unwrap(),
+            ExtValue {
+                charset: "UTF-8".to_string(),
+                language: None,
+                value: "naïve.txt".to_string(),
+            }
+        );
+        assert_eq!(
+            http::decode_ext_value(b"iso-8859-1'en'%A3%20rates", &Decoder::new()).unwrap(),
+            ExtValue {
+                charset: "ISO-8859-1".to_string(),
+                language: Some("en".to_string()),
+                value: "£ rates".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn ext_value_errors() {
+        assert!(matches!(
+            http::decode_ext_value(b"windows-1252''%80", &Decoder::new()),
+            Err(Error::UnsupportedCharsetError(charset, _)) if charset == "windows-1252"
+        ));
+        assert!(matches!(
+            http::decode_ext_value(b"''a.txt", &Decoder::new()),
+            Err(Error::UnsupportedCharsetError(charset, _)) if charset.is_empty()
+        ));
+        assert!(matches!(
+            http::decode_ext_value(b"na%C3%AFve.txt", &Decoder::new()),
+            Err(Error::ParseExtValueError(location)) if location.offset == 14
+        ));
+        assert!(matches!(
+            http::decode_ext_value(b"UTF-8''%C3%A", &Decoder::new()),
+            Err(Error::DecodePercentError(rfc2231::Error::DecodePercentError(_, location)))
+                if location.offset == 10 && location.encoded_word == "UTF-8''%C3%A"
+        ));
+    }
+
+    #[test]
+    fn params() {
+        let decoder = Decoder::new();
+        let header = http::decode_params(
+            b"attachment; filename=\"naive.txt\"; filename*=UTF-8''na%C3%AFve.txt",
+            &decoder,
+        )
+        .unwrap();
+        assert_eq!(header.value, "attachment");
+        assert_eq!(
+            header.params,
+            vec![("filename".to_string(), "naïve.txt".to_string())]
+        );
+
+        let header = http::decode_params(
+            b"attachment; Filename*=UTF-8''na%C3%AFve.txt; filename=\"naive.txt\"",
+            &decoder,
+        )
+        .unwrap();
+        assert_eq!(header.param("filename"), Some("naïve.txt"));
+    }
+
+    #[test]
+    fn invalid_ext_value_params() {
+        let decoder = Decoder::new();
+        let header = http::decode_params(
+            b"attachment; filename=\"a.txt\"; filename*=x-bogus''b%E9.txt",
+            &decoder,
+        )
+        .unwrap();
+        assert_eq!(header.param("filename"), Some("a.txt"));
+        assert_eq!(
+            header.diagnostics,
+            vec![ParamDiagnostic {
+                name: "filename".to_string(),
+                kind: ParamDiagnosticKind::InvalidExtendedValue,
+            }]
+        );
+
+        let header = http::decode_params(
+            b"attachment; filename*=UTF-8''%C3%A; filename=\"a.txt\"",
+            &decoder,
+        )
+        .unwrap();
+        assert_eq!(header.param("filename"), Some("a.txt"));
+
+        let header = http::decode_params(b"attachment; filename*=b%E9.txt", &decoder).unwrap();
+        assert_eq!(header.param("filename"), None);
+        assert_eq!(header.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn params_with_decoder() {
+        let registry = CharsetRegistry::new().decoder("UTF-8", Upper);
+        let decoder = Decoder::new().charset_registry(registry);
+        let header = http::decode_params(
+            b"attachment; filename*=UTF-8''na%C3%AFve.txt; name=\"=?utf-8?Q?a?=\"",
+            &decoder,
+        )
+        .unwrap();
+        assert_eq!(header.param("filename"), Some("NAÏVE.TXT"));
+        assert_eq!(header.param("name"), Some("A"));
+    }
+
+    #[test]
+    fn legacy_params() {
+        let decoder = Decoder::new();
+        let header = http::decode_params(
+            b"form-data; name=\"file\"; filename=\"=?UTF-8?B?bmHDr3ZlLnR4dA==?=\"",
+            &decoder,
+        )
+        .unwrap();
+        assert_eq!(header.param("name"), Some("file"));
+        assert_eq!(header.param("filename"), Some("naïve.txt"));
+    }
+}
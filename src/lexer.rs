@@ -1,4 +1,7 @@
+use std::ops::Range;
+
 use crate::lexer::State::*;
+use crate::{ast, Decoder, Location};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Token {
@@ -9,7 +12,8 @@ pub enum Token {
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
-pub type Tokens = Vec<Token>;
+pub type Span = Range<usize>;
+pub type Tokens = Vec<(Token, Span)>;
 
 enum State {
     Charset,
@@ -19,87 +23,122 @@ enum State {
 }
 
 #[derive(thiserror::Error, Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
-    #[error("the charset section is invalid or not terminated")]
-    ParseCharsetError,
-    #[error("the encoding section is invalid or not terminated")]
-    ParseEncodingError,
-    #[error("the encoded text section is invalid or not terminated")]
-    ParseEncodedTextError,
+    #[error("the charset section is invalid or not terminated {0}")]
+    ParseCharsetError(Location),
+    #[error("the encoding section is invalid or not terminated {0}")]
+    ParseEncodingError(Location),
+    #[error("the encoded text section is invalid or not terminated {0}")]
+    ParseEncodedTextError(Location),
+}
+
+fn is_whitespace(byte: &u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n')
 }
 
-pub fn run(encoded_bytes: &[u8]) -> Result<Tokens> {
-    let mut encoded_bytes_iter = encoded_bytes.iter();
-    let mut curr_byte = encoded_bytes_iter.next();
+pub fn run(encoded_bytes: &[u8], decoder: &Decoder) -> Result<Tokens> {
     let mut tokens = vec![];
     let mut state = ClearText;
+    let mut clear_buffer: Vec<u8> = vec![];
+    let mut clear_start = 0;
+    let mut charset: Vec<u8> = vec![];
+    let mut encoding: Vec<u8> = vec![];
     let mut buffer: Vec<u8> = vec![];
+    let mut word_start = 0;
+    let mut pos = 0;
 
-    const EQUAL_SYMBOL: u8 = '=' as u8;
-    const QUESTION_MARK_SYMBOL: u8 = '?' as u8;
+    const EQUAL_SYMBOL: u8 = b'=';
+    const QUESTION_MARK_SYMBOL: u8 = b'?';
 
     loop {
+        let curr_byte = encoded_bytes.get(pos);
+        let next_byte = encoded_bytes.get(pos + 1);
+        let mut malformed: Option<fn(Location) -> Error> = None;
+
         match state {
             Charset => match curr_byte {
                 Some(&QUESTION_MARK_SYMBOL) => {
                     state = Encoding;
-                    tokens.push(Token::Charset(buffer.clone()));
-                    buffer.clear();
+                    charset = std::mem::take(&mut buffer);
+                }
+                Some(b) if decoder.recover_malformed_words && is_whitespace(b) => {
+                    malformed = Some(Error::ParseCharsetError)
                 }
                 Some(b) => buffer.push(*b),
-                None => return Err(Error::ParseCharsetError),
+                None => malformed = Some(Error::ParseCharsetError),
             },
             Encoding => match curr_byte {
+                Some(&QUESTION_MARK_SYMBOL)
+                    if decoder.recover_malformed_words
+                        && ast::Encoding::from_label(&buffer).is_none() =>
+                {
+                    malformed = Some(Error::ParseEncodingError)
+                }
                 Some(&QUESTION_MARK_SYMBOL) => {
                     state = EncodedText;
-                    tokens.push(Token::Encoding(buffer.clone()));
-                    buffer.clear();
+                    encoding = std::mem::take(&mut buffer);
+                }
+                Some(b) if decoder.recover_malformed_words && is_whitespace(b) => {
+                    malformed = Some(Error::ParseEncodingError)
                 }
                 Some(b) => buffer.push(*b),
-                None => return Err(Error::ParseEncodingError),
+                None => malformed = Some(Error::ParseEncodingError),
             },
-            EncodedText => match curr_byte {
-                Some(&QUESTION_MARK_SYMBOL) => {
-                    curr_byte = encoded_bytes_iter.next();
-
-                    match curr_byte {
-                        Some(&EQUAL_SYMBOL) => {
-                            state = ClearText;
-                            tokens.push(Token::EncodedText(buffer.clone()));
-                            buffer.clear();
-                        }
-                        _ => {
-                            buffer.push(QUESTION_MARK_SYMBOL);
-                            continue;
-                        }
+            EncodedText => match (curr_byte, next_byte) {
+                (Some(&QUESTION_MARK_SYMBOL), Some(&EQUAL_SYMBOL)) => {
+                    pos += 1;
+                    state = ClearText;
+
+                    if !clear_buffer.is_empty() {
+                        let span = clear_start..clear_start + clear_buffer.len();
+                        tokens.push((Token::ClearText(std::mem::take(&mut clear_buffer)), span));
                     }
+
+                    let charset_start = word_start + "=?".len();
+                    let encoding_start = charset_start + charset.len() + "?".len();
+                    let text_start = encoding_start + encoding.len() + "?".len();
+                    tokens.push((
+                        Token::Charset(std::mem::take(&mut charset)),
+                        charset_start..encoding_start - 1,
+                    ));
+                    tokens.push((
+                        Token::Encoding(std::mem::take(&mut encoding)),
+                        encoding_start..text_start - 1,
+                    ));
+                    tokens.push((
+                        Token::EncodedText(std::mem::take(&mut buffer)),
+                        text_start..pos - 1,
+                    ));
                 }
-                Some(b) => buffer.push(*b),
-                None => return Err(Error::ParseEncodedTextError),
+                // The encoded word is not terminated before the next one
+                // starts, which happens when the header is truncated. A
+                // `=?=` is base64 padding followed by the terminator.
+                (Some(&EQUAL_SYMBOL), Some(&QUESTION_MARK_SYMBOL))
+                    if decoder.recover_malformed_words
+                        && encoded_bytes.get(pos + 2) != Some(&EQUAL_SYMBOL) =>
+                {
+                    malformed = Some(Error::ParseEncodedTextError)
+                }
+                (Some(b), _) => buffer.push(*b),
+                (None, _) => malformed = Some(Error::ParseEncodedTextError),
             },
-            ClearText => match curr_byte {
-                Some(&EQUAL_SYMBOL) => {
-                    curr_byte = encoded_bytes_iter.next();
-
-                    match curr_byte {
-                        Some(&QUESTION_MARK_SYMBOL) => {
-                            state = Charset;
-
-                            if !buffer.is_empty() {
-                                tokens.push(Token::ClearText(buffer.clone()));
-                                buffer.clear()
-                            }
-                        }
-                        _ => {
-                            buffer.push(EQUAL_SYMBOL);
-                            continue;
-                        }
+            ClearText => match (curr_byte, next_byte) {
+                (Some(&EQUAL_SYMBOL), Some(&QUESTION_MARK_SYMBOL)) => {
+                    state = Charset;
+                    word_start = pos;
+                    pos += 1;
+                }
+                (Some(b), _) => {
+                    if clear_buffer.is_empty() {
+                        clear_start = pos;
                     }
+                    clear_buffer.push(*b);
                 }
-                Some(b) => buffer.push(*b),
-                None => {
-                    if !buffer.is_empty() {
-                        tokens.push(Token::ClearText(buffer.clone()));
+                (None, _) => {
+                    if !clear_buffer.is_empty() {
+                        let span = clear_start..clear_start + clear_buffer.len();
+                        tokens.push((Token::ClearText(clear_buffer), span));
                     }
 
                     break;
@@ -107,8 +146,109 @@ pub fn run(encoded_bytes: &[u8]) -> Result<Tokens> {
             },
         }
 
-        curr_byte = encoded_bytes_iter.next();
+        if let Some(err) = malformed {
+            if !decoder.recover_malformed_words {
+                let word_end = encoded_bytes.len().min(pos + 1);
+                let encoded_word = &encoded_bytes[word_start..word_end];
+                return Err(err(Location::new(pos, word_start, encoded_word)));
+            }
+
+            // Keep the `=?` of the malformed encoded word as clear text,
+            // then lex again what follows it.
+            if clear_buffer.is_empty() {
+                clear_start = word_start;
+            }
+            clear_buffer.extend_from_slice(&encoded_bytes[word_start..word_start + 2]);
+            buffer.clear();
+            state = ClearText;
+            pos = word_start + 2;
+            continue;
+        }
+
+        pos += 1;
     }
 
     Ok(tokens)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::{self, Token::*},
+        Decoder,
+    };
+
+    fn run(encoded_str: &[u8], decoder: &Decoder) -> lexer::Result<Vec<lexer::Token>> {
+        let tokens = lexer::run(encoded_str, decoder)?;
+        Ok(tokens.into_iter().map(|(token, _)| token).collect())
+    }
+
+    #[test]
+    fn encoded_words() {
+        let decoder = Decoder::new();
+        let tokens = run(b"a =?utf-8?q?b?c?==?x?y?z?=", &decoder).unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                ClearText(b"a ".to_vec()),
+                Charset(b"utf-8".to_vec()),
+                Encoding(b"q".to_vec()),
+                EncodedText(b"b?c".to_vec()),
+                Charset(b"x".to_vec()),
+                Encoding(b"y".to_vec()),
+                EncodedText(b"z".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let decoder = Decoder::new().recover_malformed_words(true);
+        let spans = lexer::run(b"a =? =?utf-8?q?b?= c", &decoder)
+            .unwrap()
+            .into_iter()
+            .map(|(_, span)| span)
+            .collect::<Vec<_>>();
+
+        assert_eq!(spans, vec![0..5, 7..12, 13..14, 15..16, 18..20]);
+    }
+
+    #[test]
+    fn malformed() {
+        let decoder = Decoder::new();
+        assert!(lexer::run(b"Price =? ask", &decoder).is_err());
+        assert!(lexer::run(b"=?utf-8", &decoder).is_err());
+        assert!(lexer::run(b"=?utf-8?q?abc", &decoder).is_err());
+    }
+
+    #[test]
+    fn recover_malformed() {
+        let decoder = Decoder::new().recover_malformed_words(true);
+        let assert_clear = |encoded_str: &[u8]| {
+            assert_eq!(
+                run(encoded_str, &decoder).unwrap(),
+                vec![ClearText(encoded_str.to_vec())]
+            );
+        };
+
+        assert_clear(b"Price =? ask");
+        assert_clear(b"=?");
+        assert_clear(b"=?utf-8");
+        assert_clear(b"=?utf-8?q");
+        assert_clear(b"Subject =?utf-8?q?abc");
+        assert_clear(b"=?utf 8?q?abc?=");
+        assert_clear(b"Re: =?utf-8?x?str?= hi");
+
+        assert_eq!(
+            run(b"a =? b =?utf-8?q?c =?utf-8?q?d?= e", &decoder).unwrap(),
+            vec![
+                ClearText(b"a =? b =?utf-8?q?c ".to_vec()),
+                Charset(b"utf-8".to_vec()),
+                Encoding(b"q".to_vec()),
+                EncodedText(b"d".to_vec()),
+                ClearText(b" e".to_vec()),
+            ]
+        );
+    }
+}
@@ -1,8 +1,42 @@
 #![doc(html_root_url = "https://docs.rs/rfc2047-decoder/0.1.2")]
 
+mod ast;
+mod b_encoding;
+mod context;
+mod decoder;
+mod detection;
+mod diagnostic;
+mod encoder;
 mod evaluator;
+mod filename;
+mod http;
 mod lexer;
+mod location;
+mod mojibake;
+mod params;
 mod parser;
+mod q_encoding;
+mod registry;
+mod rfc2231;
+mod substitution;
+
+pub use ast::{ClearText, EncodedWord, Encoding, Segment};
+pub use b_encoding::Base64Repair;
+pub use context::Context;
+pub use decoder::{Decoder, ErrorPolicy};
+pub use detection::{Confidence, DetectedCharset};
+pub use diagnostic::{Decoded, Diagnostic, DiagnosticKind, Fallback};
+pub use encoder::Encoder;
+pub use filename::{Filename, FilenameChange, MAX_FILENAME_LEN};
+pub use http::ExtValue;
+pub use location::Location;
+pub use mojibake::RepairedMojibake;
+pub use params::{ParamDiagnostic, ParamDiagnosticKind, ParamHeader};
+pub use q_encoding::QRepair;
+pub use registry::{
+    normalize_label as normalize_charset_label, CharsetDecoder, CharsetRegistry, ResolvedCharset,
+};
+pub use substitution::SubstitutedCharset;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -14,16 +48,55 @@ pub enum Error {
     Parser(#[from] parser::Error),
     #[error(transparent)]
     Evaluate(#[from] evaluator::Error),
+    #[error(transparent)]
+    Rfc2231(#[from] rfc2231::Error),
+    #[error(transparent)]
+    Http(#[from] http::Error),
+}
+
+impl Error {
+    /// Location of the error in the header, with the encoded word it
+    /// comes from, if any.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::Lexer(
+                lexer::Error::ParseCharsetError(location)
+                | lexer::Error::ParseEncodingError(location)
+                | lexer::Error::ParseEncodedTextError(location),
+            ) => Some(location),
+            Error::Parser(
+                parser::Error::EncodedWordNotSeparatedError(location)
+                | parser::Error::EncodedWordTooLongError(_, location)
+                | parser::Error::InvalidEncodedTextError(location)
+                | parser::Error::UnknownEncodingError(_, location),
+            ) => Some(location),
+            Error::Evaluate(
+                evaluator::Error::UndecodableEncodedWordError(location)
+                | evaluator::Error::InvalidClearTextError(location),
+            ) => Some(location),
+            Error::Rfc2231(
+                rfc2231::Error::DecodePercentError(_, location)
+                | rfc2231::Error::ParseExtendedValueError(_, location),
+            )
+            | Error::Http(
+                http::Error::ParseExtValueError(location)
+                | http::Error::UnsupportedCharsetError(_, location)
+                | http::Error::DecodePercentError(
+                    rfc2231::Error::DecodePercentError(_, location)
+                    | rfc2231::Error::ParseExtendedValueError(_, location),
+                ),
+            ) => Some(location),
+            _ => None,
+        }
+    }
 }
 
 /// Decode a RFC 2047 MIME Message Header.
 ///
 /// ```rust
-/// fn main() {
-///     match rfc2047_decoder::decode("=?utf8?q?str_with_spaces?=".as_bytes()) {
-///         Ok(s) => println!("{}", s),
-///         Err(err) => panic!(err),
-///     }
+/// match rfc2047_decoder::decode("=?utf8?q?str_with_spaces?=".as_bytes()) {
+///     Ok(s) => println!("{}", s),
+///     Err(err) => panic!("{}", err),
 /// }
 /// ```
 ///
@@ -31,17 +104,310 @@ pub enum Error {
 ///
 /// The function can return an error if the lexer,
 /// the parser or the evaluator encounters an error.
+/// See [`Decoder`] for more options.
 pub fn decode(encoded_str: &[u8]) -> Result<String> {
-    let tokens = lexer::run(&encoded_str)?;
-    let ats = parser::run(&tokens)?;
-    let decoded_str = evaluator::run(&ats)?;
+    Decoder::new().decode(encoded_str)
+}
+
+/// Decode a RFC 2047 MIME Message Header, with the problems met while
+/// decoding it.
+///
+/// ```rust
+/// use rfc2047_decoder::{DiagnosticKind, Fallback};
+///
+/// let decoded = rfc2047_decoder::decode_with_diagnostics(b"=?x-unknown?Q?str?=").unwrap();
+///
+/// assert_eq!(decoded.text, "str");
+/// assert_eq!(
+///     decoded.diagnostics[0].kind,
+///     DiagnosticKind::UnknownCharset("x-unknown".to_string())
+/// );
+/// assert_eq!(decoded.diagnostics[0].span, 0..19);
+/// assert_eq!(decoded.diagnostics[0].fallback, Fallback::Ascii);
+/// ```
+///
+/// Unknown charsets, invalid encoded texts and invalid UTF-8 clear text
+/// do not fail the decoding: a fallback is applied, and reported as a
+/// [`Diagnostic`].
+///
+/// # Errors
+///
+/// The function can return an error if the lexer or the parser
+/// encounters an error. See [`Decoder`] for more options.
+pub fn decode_with_diagnostics(encoded_str: &[u8]) -> Result<Decoded> {
+    Decoder::new().decode_with_diagnostics(encoded_str)
+}
+
+/// Parse a RFC 2047 MIME Message Header into its clear text and encoded
+/// word segments.
+///
+/// ```rust
+/// use rfc2047_decoder::{Encoding, Segment};
+///
+/// let segments = rfc2047_decoder::parse(b"Re: =?iso-8859-1*fr?Q?caf=E9?=").unwrap();
+///
+/// assert_eq!(segments[0].text(), "Re: ");
+/// match &segments[1] {
+///     Segment::EncodedWord(word) => {
+///         assert_eq!(word.charset, "iso-8859-1");
+///         assert_eq!(word.language.as_deref(), Some("fr"));
+///         assert_eq!(word.encoding, Some(Encoding::Q));
+///         assert_eq!(word.text, "café");
+///         assert_eq!(word.span, 4..30);
+///     }
+///     segment => panic!("unexpected segment {:?}", segment),
+/// }
+/// ```
+///
+/// Segments are returned in order of appearance, with the byte span of
+/// each of them in the header. Whitespace separating encoded words is
+/// not part of any segment, unless one of them is kept verbatim, see
+/// [`ErrorPolicy::Verbatim`], and the concatenated texts of the segments
+/// are the output of [`decode`].
+///
+/// # Errors
+///
+/// The function can return an error if the lexer or the parser
+/// encounters an error. See [`Decoder`] for more options.
+pub fn parse(encoded_str: &[u8]) -> Result<Vec<Segment>> {
+    Decoder::new().parse(encoded_str)
+}
+
+/// Decode RFC 2231 MIME parameters, like the ones of the
+/// `Content-Type` and `Content-Disposition` headers.
+///
+/// ```rust
+/// let params = rfc2047_decoder::decode_params(&[
+///     ("filename*0*", "utf-8''%E6%97%A5"),
+///     ("filename*1*", "%E6%9C%AC.pdf"),
+///     ("filename", "fallback.pdf"),
+/// ])
+/// .unwrap();
+///
+/// assert_eq!(params, vec![("filename".to_string(), "日本.pdf".to_string())]);
+/// ```
+///
+/// Parameters are returned in order of appearance, with their names
+/// lowercased and their continuations reassembled. When a parameter is
+/// given in several forms, continuations are preferred over extended
+/// values, which are preferred over regular values.
+///
+/// # Errors
+///
+/// The function can return an error if an extended value has no
+/// charset or contains an invalid percent-encoded octet, and the
+/// parameter has no regular value to fall back to. See [`Decoder`] for
+/// more options.
+pub fn decode_params<N: AsRef<str>, V: AsRef<[u8]>>(
+    params: &[(N, V)],
+) -> Result<Vec<(String, String)>> {
+    Decoder::new().decode_params(params)
+}
+
+/// Parse and decode the value of a MIME header with parameters, like
+/// `Content-Type` or `Content-Disposition`.
+///
+/// ```rust
+/// use rfc2047_decoder::Decoder;
+///
+/// let header_value = b"application/pdf; name=\"=?UTF-8?B?5pel5pys?=.pdf\"";
+/// let header = Decoder::new()
+///     .lenient_params(true)
+///     .parse_params(header_value)
+///     .unwrap();
+///
+/// assert_eq!(header.value, "application/pdf");
+/// assert_eq!(header.param("Name"), Some("日本.pdf"));
+/// ```
+///
+/// Parameters are separated by `;`, and their quoted values unquoted
+/// before being decoded with [`decode_params`].
+///
+/// # Errors
+///
+/// The function can return an error if a parameter cannot be decoded.
+/// See [`Decoder`] for more options.
+pub fn parse_params(header_value: &[u8]) -> Result<ParamHeader> {
+    Decoder::new().parse_params(header_value)
+}
 
-    Ok(decoded_str)
+/// Decode a RFC 8187 ext-value, as found in the extended parameters of
+/// HTTP headers.
+///
+/// ```rust
+/// let ext_value = rfc2047_decoder::decode_ext_value(b"UTF-8'en'na%C3%AFve.txt").unwrap();
+///
+/// assert_eq!(ext_value.charset, "UTF-8");
+/// assert_eq!(ext_value.language.as_deref(), Some("en"));
+/// assert_eq!(ext_value.value, "naïve.txt");
+/// ```
+///
+/// # Errors
+///
+/// The function can return an error if the ext-value has no charset,
+/// if its charset is neither UTF-8 nor ISO-8859-1, or if it contains an
+/// invalid percent-encoded octet.
+pub fn decode_ext_value(ext_value: &[u8]) -> Result<ExtValue> {
+    Ok(http::decode_ext_value(ext_value, &Decoder::new())?)
+}
+
+/// Parse and decode the value of a HTTP header with parameters, like
+/// `Content-Disposition`, as defined in RFC 8187 and RFC 6266.
+///
+/// ```rust
+/// let header = rfc2047_decoder::decode_http_params(
+///     b"attachment; filename=\"naive.txt\"; filename*=UTF-8''na%C3%AFve.txt",
+/// )
+/// .unwrap();
+///
+/// assert_eq!(header.value, "attachment");
+/// assert_eq!(header.param("filename"), Some("naïve.txt"));
+/// ```
+///
+/// Extended parameters are preferred over regular ones. Regular values
+/// containing RFC 2047 encoded words, as sent by legacy browsers, are
+/// decoded as well. HTTP has no parameter continuations.
+///
+/// An ext-value that cannot be decoded, see [`decode_ext_value`], is
+/// skipped and the regular value of the parameter, if any, is used
+/// instead. See [`Decoder`] for more options.
+///
+/// ```rust
+/// let header = rfc2047_decoder::decode_http_params(
+///     b"attachment; filename=\"a.txt\"; filename*=x-bogus''b%E9.txt",
+/// )
+/// .unwrap();
+///
+/// assert_eq!(header.param("filename"), Some("a.txt"));
+/// ```
+pub fn decode_http_params(header_value: &[u8]) -> Result<ParamHeader> {
+    Decoder::new().decode_http_params(header_value)
+}
+
+/// Decode the filename of a `Content-Disposition` header, or the name of
+/// a `Content-Type` header, and sanitise it.
+///
+/// ```rust
+/// let filename = rfc2047_decoder::decode_filename(
+///     b"attachment; filename=\"=?utf-8?Q?..=2F..=2F=E6=97=A5=E6=9C=AC.pdf?=\"",
+/// )
+/// .unwrap()
+/// .unwrap();
+///
+/// assert_eq!(filename.raw, "../../日本.pdf");
+/// assert_eq!(filename.name, "日本.pdf");
+/// ```
+///
+/// The filename may be made of encoded words, even in a quoted value,
+/// of RFC 2231 extended values or of raw text. It is then sanitised
+/// with [`sanitize_filename`]. Returns `None` when the header has no
+/// filename.
+///
+/// # Errors
+///
+/// The function can return an error if the parameters cannot be
+/// decoded, see [`decode_params`]. See [`Decoder`] for more options.
+pub fn decode_filename(header_value: &[u8]) -> Result<Option<Filename>> {
+    Decoder::new().decode_filename(header_value)
+}
+
+/// Sanitise a decoded filename, so that it is safe to use as a file name
+/// on any filesystem.
+///
+/// ```rust
+/// use rfc2047_decoder::FilenameChange;
+///
+/// let filename = rfc2047_decoder::sanitize_filename("NUL.txt\r\n");
+///
+/// assert_eq!(filename.name, "_NUL.txt");
+/// assert_eq!(
+///     filename.changes,
+///     vec![FilenameChange::ControlCharRemoved, FilenameChange::ReservedNameEscaped]
+/// );
+/// ```
+///
+/// Directories, control characters and bidirectional formatting
+/// characters are removed, characters forbidden by Windows are replaced
+/// by `_`, names reserved by Windows are escaped and the result is at
+/// most [`MAX_FILENAME_LEN`] bytes long. See [`FilenameChange`] for the
+/// full list of changes.
+pub fn sanitize_filename(raw: &str) -> Filename {
+    filename::sanitize(raw)
+}
+
+/// Encode a string into RFC 2047 encoded words.
+///
+/// ```rust
+/// let encoded_str = rfc2047_decoder::encode("str with special çhàrß");
+/// assert_eq!(
+///     rfc2047_decoder::decode(encoded_str.as_bytes()).unwrap(),
+///     "str with special çhàrß"
+/// );
+/// ```
+///
+/// The output is made of UTF-8 encoded words separated by a space.
+/// Each word is Q or B encoded, whichever is the shortest, and is at
+/// most 75 characters long. See [`Encoder`] for more options.
+pub fn encode(decoded_str: &str) -> String {
+    Encoder::new().encode(decoded_str)
+}
+
+/// Encode a string into the folded value of a RFC 2047 MIME Message
+/// Header.
+///
+/// ```rust
+/// let subject = "str with special çhàrß ".repeat(4);
+/// let folded_str = rfc2047_decoder::encode_header("Subject", &subject);
+/// let header = format!("Subject: {}", folded_str);
+///
+/// assert!(header.split("\r\n").all(|line| line.len() <= 76));
+/// assert_eq!(
+///     rfc2047_decoder::decode(folded_str.as_bytes()).unwrap(),
+///     subject
+/// );
+/// ```
+///
+/// The field name is only used to compute the room left on the first
+/// line: it is not part of the output. Lines, the `field_name: ` prefix
+/// included, are at most 76 characters long and are folded with a CRLF
+/// followed by a space. See [`Encoder`] for more options.
+pub fn encode_header(field_name: &str, decoded_str: &str) -> String {
+    Encoder::new().encode_header(field_name, decoded_str)
+}
+
+/// Encode a MIME parameter, like the `filename` of a
+/// `Content-Disposition` header, as defined in RFC 2231.
+///
+/// ```rust
+/// let params = rfc2047_decoder::encode_param("filename", "naïve.txt");
+/// assert_eq!(params, vec!["filename*=utf-8''na%C3%AFve.txt"]);
+///
+/// let header = format!("Content-Disposition: attachment;\r\n {}", params.join(";\r\n "));
+/// ```
+///
+/// Values too long to fit on a line are split into continuations, each
+/// of them meant to be on its own line. See [`Encoder`] for more
+/// options.
+pub fn encode_param(name: &str, value: &str) -> Vec<String> {
+    Encoder::new().encode_param(name, value)
+}
+
+/// Tell whether an encoded word is legal at the given position of a
+/// header, according to RFC 2047 section 5.
+///
+/// ```rust
+/// use rfc2047_decoder::{is_legal_encoded_word, Context};
+///
+/// assert!(is_legal_encoded_word(b"=?utf-8?Q?a(b)?=", Context::Text));
+/// assert!(!is_legal_encoded_word(b"=?utf-8?Q?a(b)?=", Context::Comment));
+/// ```
+pub fn is_legal_encoded_word(encoded_word: &[u8], context: Context) -> bool {
+    context::is_legal(encoded_word, context)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::decode;
+    use crate::{decode, decode_ext_value, decode_params};
 
     fn assert_ok(decoded_str: &str, encoded_str: &str) {
         assert!(if let Ok(s) = decode(encoded_str.as_bytes()) {
@@ -64,6 +430,7 @@ mod tests {
     #[test]
     fn clear_with_spaces() {
         assert_ok("str with spaces", "str with spaces");
+        assert_ok("a b", "a\n b");
     }
 
     #[test]
@@ -119,4 +486,44 @@ mod tests {
             "=?utf8?b?c3RyIHdpdGggc3BlY2lhbCDDp2jDoHLDnw==?=",
         );
     }
+
+    #[test]
+    fn utf8_split_char() {
+        assert_ok("é", "=?UTF-8?B?ww==?= =?UTF-8?B?qQ==?=");
+        assert_ok("é", "=?utf-8?Q?=C3?=\r\n =?UTF-8?Q?=A9?=");
+        assert_ok("é", "=?utf-8?Q?=C3?= =?UTF-8?B?qQ==?=");
+    }
+
+    #[test]
+    fn rfc2231_language() {
+        assert_ok("café", "=?utf-8*en-US?Q?caf=C3=A9?=");
+        assert_ok("café", "=?ISO-8859-1*fr?Q?caf=E9?=");
+        assert_ok("é", "=?utf-8*fr?Q?=C3?= =?utf-8*fr?Q?=A9?=");
+    }
+
+    #[test]
+    fn iso_2022_jp_split_word() {
+        assert_ok(
+            "日本語",
+            "=?iso-2022-jp?B?GyRCRnw=?= =?iso-2022-jp?B?S1w4bBsoQg==?=",
+        );
+    }
+
+    #[test]
+    fn different_charsets() {
+        assert_ok("\u{FFFD}é", "=?utf-8?B?ww==?= =?iso-8859-1?Q?=E9?=");
+        assert_ok("é\u{FFFD}", "=?iso-8859-1?Q?=E9?= =?utf-8?B?ww==?=");
+    }
+
+    #[test]
+    fn param_error_locations() {
+        let err = decode_params(&[("filename*", "utf-8''%E6%9")]).unwrap_err();
+        assert_eq!(
+            err.location().unwrap().to_string(),
+            "at byte 10\n  utf-8''%E6%9\n            ^"
+        );
+
+        let err = decode_ext_value(b"windows-1252''%80").unwrap_err();
+        assert_eq!(err.location().unwrap().offset, 0);
+    }
 }
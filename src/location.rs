@@ -0,0 +1,104 @@
+use std::fmt;
+
+/// Maximum number of characters of the encoded word shown on each side
+/// of the caret.
+const MAX_SNIPPET_HALF_LEN: usize = 30;
+
+/// Location of an error in a header, with the raw encoded word it comes
+/// from. Errors of parameter values are located in the raw value, as if
+/// it were the header and its only encoded word.
+///
+/// Its `Display` form prints the byte offset, followed by a snippet of
+/// the encoded word with a caret under the faulty byte:
+///
+/// ```text
+/// at byte 11
+///   =?utf-8?q?a b?=
+///              ^
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// Byte offset of the error in the header.
+    pub offset: usize,
+    /// Byte offset of the encoded word in the header.
+    pub word_offset: usize,
+    /// Raw encoded word, lossily decoded as UTF-8.
+    pub encoded_word: String,
+    /// Offset of the error in `encoded_word`, in characters.
+    pub column: usize,
+}
+
+impl Location {
+    pub(crate) fn new(offset: usize, word_offset: usize, encoded_word: &[u8]) -> Self {
+        // The column is counted on the raw bytes, as each invalid sequence
+        // is replaced by a single character in the lossy encoded word.
+        let column = offset.saturating_sub(word_offset).min(encoded_word.len());
+        let column = String::from_utf8_lossy(&encoded_word[..column])
+            .chars()
+            .count();
+
+        Self {
+            offset,
+            word_offset,
+            encoded_word: String::from_utf8_lossy(encoded_word).to_string(),
+            column,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let column = self.column;
+        let skipped = column.saturating_sub(MAX_SNIPPET_HALF_LEN);
+        let snippet = self
+            .encoded_word
+            .chars()
+            .skip(skipped)
+            .take(MAX_SNIPPET_HALF_LEN * 2)
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect::<String>();
+
+        write!(
+            f,
+            "at byte {}\n  {}\n  {}^",
+            self.offset,
+            snippet,
+            " ".repeat(column - skipped)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::location::Location;
+
+    #[test]
+    fn display() {
+        let location = Location::new(13, 2, b"=?utf-8?q?a b?=");
+        assert_eq!(
+            location.to_string(),
+            "at byte 13\n  =?utf-8?q?a b?=\n             ^"
+        );
+
+        let location = Location::new(9, 0, b"=?utf-8\r\n");
+        assert_eq!(location.to_string(), "at byte 9\n  =?utf-8  \n           ^");
+
+        let location = Location::new(7, 2, b"=?\xe2\x82?x?a?=");
+        assert_eq!(location.column, 4);
+        assert_eq!(
+            location.to_string(),
+            "at byte 7\n  =?\u{fffd}?x?a?=\n      ^"
+        );
+    }
+
+    #[test]
+    fn display_long_word() {
+        let encoded_word = format!("=?utf-8?q?{}?=", "a".repeat(100));
+        let location = Location::new(75, 0, encoded_word.as_bytes());
+        let snippet = location.to_string();
+        let lines = snippet.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[1], format!("  {}", "a".repeat(60)));
+        assert_eq!(lines[2].find('^'), Some(2 + 30));
+    }
+}
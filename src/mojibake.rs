@@ -0,0 +1,170 @@
+use std::ops::Range;
+
+use crate::substitution;
+
+/// Characters of windows-1252 bytes 0x80 to 0x9F, as decoded by the
+/// WHATWG Encoding Standard. Unassigned bytes are decoded as C1 controls.
+#[rustfmt::skip]
+const WINDOWS_1252_C1_CHARS: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// Mojibake repaired in a segment of a header, see
+/// [`crate::Decoder::repair_mojibake`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepairedMojibake {
+    /// Decoded text of the segment, before the repair.
+    pub text: String,
+    /// Repaired text of the segment.
+    pub repaired: String,
+    /// Charset the UTF-8 bytes were wrongly decoded with upstream,
+    /// either `windows-1252` or `ISO-8859-1`.
+    pub charset: String,
+    /// Byte span of the segment in the header.
+    pub span: Range<usize>,
+}
+
+/// Encode the given char back to the windows-1252 or ISO-8859-1 byte it
+/// was decoded from.
+fn encode_byte(c: char) -> Option<u8> {
+    match c as u32 {
+        0x00..=0xFF => Some(c as u8),
+        _ => WINDOWS_1252_C1_CHARS
+            .iter()
+            .position(|c1| *c1 == c)
+            .map(|i| 0x80 + i as u8),
+    }
+}
+
+/// Tell whether the given char leads the mojibake of a two or three
+/// bytes UTF-8 sequence of the Latin-1 Supplement, Latin Extended-A or
+/// General Punctuation blocks, the most frequent ones.
+fn is_lead(c: char) -> bool {
+    matches!(c, 'Ã' | 'Â' | 'â')
+}
+
+/// Tell whether the given char was decoded from a UTF-8 continuation
+/// byte.
+fn is_continuation(c: char) -> bool {
+    encode_byte(c).is_some_and(|b| (0x80..=0xBF).contains(&b))
+}
+
+/// Count the suspicious chars of the given text: each mojibake lead
+/// followed by a continuation counts once, like non-ASCII whitespace
+/// and C1 controls.
+fn suspicious_count(text: &str) -> usize {
+    let mut count = 0;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if is_lead(c) && chars.peek().copied().is_some_and(is_continuation) {
+            chars.next();
+            count += 1;
+        } else if !c.is_ascii() && (c.is_whitespace() || c.is_control()) {
+            count += 1;
+        }
+    }
+
+    count
+}
+
+/// Reverse UTF-8 text decoded as windows-1252 or ISO-8859-1, like
+/// `CafÃ©`, returning the repaired text and the charset it was wrongly
+/// decoded with.
+///
+/// The repair is conservative: the text must contain a mojibake lead
+/// followed by a continuation, encode back to bytes forming valid UTF-8
+/// without control characters, which legit windows-1252 text very
+/// rarely does, and the repaired text must be less suspicious.
+pub(crate) fn repair(text: &str) -> Option<(String, &'static str)> {
+    let suspicious = suspicious_count(text);
+    let has_lead = text
+        .chars()
+        .zip(text.chars().skip(1))
+        .any(|(a, b)| is_lead(a) && is_continuation(b));
+    if !has_lead {
+        return None;
+    }
+
+    let bytes = text.chars().map(encode_byte).collect::<Option<Vec<_>>>()?;
+    let repaired = String::from_utf8(bytes).ok()?;
+    if substitution::has_controls(&repaired) || suspicious_count(&repaired) >= suspicious {
+        return None;
+    }
+
+    let charset = if text.chars().all(|c| c as u32 <= 0xFF) {
+        "ISO-8859-1"
+    } else {
+        "windows-1252"
+    };
+
+    Some((repaired, charset))
+}
+
+/// Repair the joined text of adjacent segments, then give each segment
+/// the characters that are fully repaired once its own characters are
+/// known, the way consecutive encoded words are decoded.
+pub(crate) fn repair_joined(texts: &[&str]) -> Option<(Vec<String>, &'static str)> {
+    let (repaired, charset) = repair(&texts.concat())?;
+    let mut repaired_texts = vec![];
+    let mut start = 0;
+    let mut end = 0;
+
+    // Each char of the text encodes back to a single byte of the
+    // repaired text.
+    for text in &texts[..texts.len() - 1] {
+        end += text.chars().count();
+        let mut boundary = end;
+        while !repaired.is_char_boundary(boundary) {
+            boundary -= 1;
+        }
+        let boundary = boundary.max(start);
+        repaired_texts.push(repaired[start..boundary].to_string());
+        start = boundary;
+    }
+    repaired_texts.push(repaired[start..].to_string());
+
+    Some((repaired_texts, charset))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mojibake;
+
+    #[test]
+    fn repair() {
+        assert_eq!(
+            mojibake::repair("CafÃ© rÃ©union"),
+            Some(("Café réunion".to_string(), "ISO-8859-1"))
+        );
+        assert_eq!(
+            mojibake::repair("â€œquotedâ€\u{9D} â€” æ—¥æœ¬"),
+            Some(("“quoted” — 日本".to_string(), "windows-1252"))
+        );
+    }
+
+    #[test]
+    fn repair_conservative() {
+        assert_eq!(mojibake::repair("ascii"), None);
+        assert_eq!(mojibake::repair("Café réunion"), None);
+        assert_eq!(mojibake::repair("naïve Ã"), None);
+        assert_eq!(mojibake::repair("CafÃ© 日本"), None);
+        assert_eq!(mojibake::repair("CafÃ© \u{FFFD}"), None);
+        assert_eq!(mojibake::repair("Â\u{85}"), None);
+        assert_eq!(mojibake::repair("Â\u{A0}"), None);
+        assert_eq!(mojibake::repair("Ä\u{8D}"), None);
+    }
+
+    #[test]
+    fn repair_joined() {
+        assert_eq!(
+            mojibake::repair_joined(&["CafÃ", "©", " rÃ©union"]),
+            Some((
+                vec!["Caf".to_string(), "é".to_string(), " réunion".to_string()],
+                "ISO-8859-1"
+            ))
+        );
+        assert_eq!(mojibake::repair_joined(&["Â", "\u{A0}"]), None);
+    }
+}
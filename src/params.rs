@@ -0,0 +1,226 @@
+use crate::{rfc2231, Decoder};
+
+/// Value of a MIME header with parameters, like `Content-Type` or
+/// `Content-Disposition`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParamHeader {
+    /// Value preceding the parameters, like `text/plain`.
+    pub value: String,
+    /// Decoded parameters, with their names lowercased.
+    pub params: Vec<(String, String)>,
+    /// Problems met while decoding the parameters, in order of
+    /// appearance. Empty when the parameters decoded cleanly.
+    pub diagnostics: Vec<ParamDiagnostic>,
+}
+
+/// Kind of problem met while decoding a parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamDiagnosticKind {
+    /// The extended value, or the continuations, of the parameter
+    /// cannot be decoded. The regular value is used instead, or the
+    /// parameter is dropped when it has none.
+    InvalidExtendedValue,
+    /// The encoded words of the regular value cannot be decoded, and
+    /// are kept as is.
+    InvalidEncodedWords,
+    /// The value is not valid UTF-8, invalid sequences are replaced by
+    /// U+FFFD REPLACEMENT CHARACTER.
+    InvalidUtf8,
+}
+
+/// Problem met while decoding a parameter, which did not prevent
+/// decoding the header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParamDiagnostic {
+    /// Lowercased name of the parameter, without section nor `*`.
+    pub name: String,
+    /// What went wrong.
+    pub kind: ParamDiagnosticKind,
+}
+
+impl ParamHeader {
+    /// Find the value of the given parameter, ignoring case.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn is_whitespace(byte: &u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n')
+}
+
+fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| !is_whitespace(b));
+    let end = bytes.iter().rposition(|b| !is_whitespace(b));
+
+    match (start, end) {
+        (Some(start), Some(end)) => &bytes[start..=end],
+        _ => &[],
+    }
+}
+
+fn find(bytes: &[u8], pos: usize, pred: impl Fn(&u8) -> bool) -> usize {
+    bytes[pos..]
+        .iter()
+        .position(pred)
+        .map_or(bytes.len(), |i| pos + i)
+}
+
+/// Read the quoted string starting at the given position, resolving
+/// quoted pairs and unfolding lines. An unterminated quoted string
+/// runs until the end of the header.
+fn read_quoted_string(bytes: &[u8], mut pos: usize) -> (Vec<u8>, usize) {
+    let mut value = vec![];
+    pos += 1;
+
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' if pos + 1 < bytes.len() => {
+                value.push(bytes[pos + 1]);
+                pos += 2;
+            }
+            b'"' => return (value, pos + 1),
+            b'\r' | b'\n' => pos += 1,
+            b => {
+                value.push(b);
+                pos += 1;
+            }
+        }
+    }
+
+    (value, pos)
+}
+
+/// Split a header value into its value and its raw parameters, as
+/// defined in RFC 2045 section 5.1. Parameters without a value are
+/// ignored.
+pub fn split(header_value: &[u8]) -> (&[u8], Vec<(String, Vec<u8>)>) {
+    let mut pos = find(header_value, 0, |b| *b == b';');
+    let value = trim(&header_value[..pos]);
+    let mut params = vec![];
+
+    while pos < header_value.len() {
+        pos += 1;
+        let name_end = find(header_value, pos, |b| *b == b'=' || *b == b';');
+        if header_value.get(name_end) != Some(&b'=') {
+            pos = name_end;
+            continue;
+        }
+
+        let name = trim(&header_value[pos..name_end]);
+        pos = find(header_value, name_end + 1, |b| !is_whitespace(b));
+
+        let value = if header_value.get(pos) == Some(&b'"') {
+            let (value, end) = read_quoted_string(header_value, pos);
+            pos = find(header_value, end, |b| *b == b';');
+            value
+        } else {
+            let end = find(header_value, pos, |b| *b == b';');
+            let value = trim(&header_value[pos..end]).to_vec();
+            pos = end;
+            value
+        };
+
+        if !name.is_empty() {
+            params.push((String::from_utf8_lossy(name).to_string(), value));
+        }
+    }
+
+    (value, params)
+}
+
+pub fn parse(header_value: &[u8], decoder: &Decoder) -> rfc2231::Result<ParamHeader> {
+    let (value, params) = split(header_value);
+    let mut diagnostics = vec![];
+    let params = rfc2231::decode_params_reporting(&params, decoder, &mut diagnostics)?;
+
+    Ok(ParamHeader {
+        value: String::from_utf8_lossy(value).to_string(),
+        params,
+        diagnostics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{params, Decoder};
+
+    fn assert_split(header_value: &str, value: &str, params: &[(&str, &str)]) {
+        let (split_value, split_params) = params::split(header_value.as_bytes());
+        let split_params = split_params
+            .iter()
+            .map(|(name, value)| (name.as_str(), std::str::from_utf8(value).unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(std::str::from_utf8(split_value).unwrap(), value);
+        assert_eq!(split_params, params);
+    }
+
+    #[test]
+    fn split() {
+        assert_split("text/plain", "text/plain", &[]);
+        assert_split(
+            "text/plain; charset=utf-8",
+            "text/plain",
+            &[("charset", "utf-8")],
+        );
+        assert_split(
+            " attachment ;\r\n\tfilename = \"a; b.pdf\" ; size=12;",
+            "attachment",
+            &[("filename", "a; b.pdf"), ("size", "12")],
+        );
+    }
+
+    #[test]
+    fn split_quoted_string() {
+        assert_split(
+            r#"attachment; filename="a \"b\" \\ c.pdf""#,
+            "attachment",
+            &[("filename", r#"a "b" \ c.pdf"#)],
+        );
+        assert_split(
+            "attachment; filename=\"a\r\n b.pdf\"",
+            "attachment",
+            &[("filename", "a b.pdf")],
+        );
+        assert_split(
+            "attachment; filename=\"a.pdf",
+            "attachment",
+            &[("filename", "a.pdf")],
+        );
+    }
+
+    #[test]
+    fn split_malformed() {
+        assert_split("attachment; inline; =a; b=", "attachment", &[("b", "")]);
+        assert_split("", "", &[]);
+    }
+
+    #[test]
+    fn lenient_params() {
+        let header_value = b"application/pdf; name=\"=?UTF-8?B?5pel5pys?=.pdf\"";
+
+        let header = params::parse(header_value, &Decoder::new()).unwrap();
+        assert_eq!(header.param("name"), Some("=?UTF-8?B?5pel5pys?=.pdf"));
+
+        let decoder = Decoder::new().lenient_params(true);
+        let header = params::parse(header_value, &decoder).unwrap();
+        assert_eq!(header.value, "application/pdf");
+        assert_eq!(header.param("NAME"), Some("日本.pdf"));
+    }
+
+    #[test]
+    fn lenient_params_continuations() {
+        let header_value = b"attachment; filename*0=\"=?utf-8?B?5pel\"; filename*1=\"5pys?=\"";
+        let decoder = Decoder::new().lenient_params(true);
+        let header = params::parse(header_value, &decoder).unwrap();
+        assert_eq!(header.param("filename"), Some("日本"));
+
+        let header_value = b"attachment; filename=\"=?utf-8?q?unterminated\"";
+        let header = params::parse(header_value, &decoder).unwrap();
+        assert_eq!(header.param("filename"), Some("=?utf-8?q?unterminated"));
+    }
+}
@@ -1,14 +1,24 @@
-use crate::lexer::Tokens;
+use crate::ast::{self, Encoding};
+use crate::lexer::{Span, Token, Tokens};
+use crate::{Decoder, Location};
 
 #[derive(Debug, Clone)]
 pub struct EncodedBytes {
     pub charset: Vec<u8>,
-    pub encoding: char,
+    pub language: Option<Vec<u8>>,
+    pub encoding: Option<Encoding>,
+    pub encoding_label: Vec<u8>,
     pub bytes: Vec<u8>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClearBytes {
+    pub bytes: Vec<u8>,
+    pub span: Span,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
-pub type ClearBytes = Vec<u8>;
 
 #[derive(Debug, Clone)]
 pub enum Node {
@@ -19,50 +29,185 @@ pub enum Node {
 pub type Ast = Vec<Node>;
 
 #[derive(thiserror::Error, Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
-    #[error(transparent)]
-    DecodeUtf8Error(#[from] std::str::Utf8Error),
+    #[error("the encoded word is not separated from the surrounding text by whitespace {0}")]
+    EncodedWordNotSeparatedError(Location),
+    #[error("the encoded word is {0} characters long, the maximum is 75, {1}")]
+    EncodedWordTooLongError(usize, Location),
+    #[error("the encoded text contains whitespace, `?` or non printable characters {0}")]
+    InvalidEncodedTextError(Location),
+    #[error("the encoding {0:?} is neither Q nor B {1}")]
+    UnknownEncodingError(String, Location),
 }
 
-fn first_char_of(vec: &[u8]) -> Result<char> {
-    match std::str::from_utf8(vec)?.to_uppercase().chars().next() {
-        Some(c) => Ok(c),
-        None => Ok('Q'),
+const MAX_ENCODED_WORD_LEN: usize = 75;
+
+/// Split the language suffix off the charset, as defined in RFC 2231
+/// section 5: `charset*language`.
+fn split_language(charset: &[u8]) -> (&[u8], Option<&[u8]>) {
+    match charset.iter().position(|b| *b == b'*') {
+        Some(i) => (&charset[..i], Some(&charset[i + 1..])),
+        None => (charset, None),
     }
 }
 
-pub fn run(tokens: &Tokens) -> Result<Ast> {
-    let mut curr_charset: &Vec<u8> = &vec![];
-    let mut curr_encoding: char = 'Q';
-    let mut ast: Ast = vec![];
+fn is_whitespace(byte: &u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n')
+}
 
-    const CR: u8 = '\r' as u8;
-    const LF: u8 = '\n' as u8;
-    const SPACE: u8 = ' ' as u8;
+/// Tell whether the given byte can precede an encoded word, as stated
+/// in RFC 2047 section 5 (2): an encoded word in a comment is only
+/// delimited from ctext, not from the parenthesis.
+fn separates_before(byte: &u8) -> bool {
+    is_whitespace(byte) || *byte == b'('
+}
 
-    for token in tokens {
+/// Tell whether the given byte can follow an encoded word.
+fn separates_after(byte: &u8) -> bool {
+    is_whitespace(byte) || *byte == b')'
+}
+
+/// Locate the given byte offset in the encoded word the token at the
+/// given index belongs to, rebuilding the raw encoded word from its
+/// tokens.
+fn locate(tokens: &Tokens, i: usize, offset: usize) -> Location {
+    use crate::lexer::Token::*;
+
+    let start = tokens[..=i]
+        .iter()
+        .rposition(|(token, _)| matches!(token, Charset(_)))
+        .unwrap_or(i);
+    let mut encoded_word = b"=?".to_vec();
+
+    for (token, _) in tokens[start..].iter().take(3) {
+        match token {
+            Charset(bytes) | Encoding(bytes) => {
+                encoded_word.extend_from_slice(bytes);
+                encoded_word.push(b'?');
+            }
+            EncodedText(bytes) => {
+                encoded_word.extend_from_slice(bytes);
+                encoded_word.extend_from_slice(b"?=");
+            }
+            ClearText(_) => break,
+        }
+    }
+
+    let word_offset = tokens[start].1.start.saturating_sub("=?".len());
+    Location::new(offset, word_offset, &encoded_word)
+}
+
+/// Check that the tokens strictly conform to RFC 2047.
+fn check_strict(tokens: &Tokens) -> Result<()> {
+    let mut prev_token: Option<&Token> = None;
+    let mut word_len = 0;
+
+    for (i, (token, span)) in tokens.iter().enumerate() {
         use crate::lexer::Token::*;
 
         match token {
             Charset(charset) => {
-                curr_charset = &charset;
+                let word_offset = span.start - "=?".len();
+                match prev_token {
+                    Some(EncodedText(_)) => {
+                        let location = locate(tokens, i, word_offset);
+                        return Err(Error::EncodedWordNotSeparatedError(location));
+                    }
+                    Some(ClearText(clear_bytes))
+                        if !clear_bytes.last().is_some_and(separates_before) =>
+                    {
+                        let location = locate(tokens, i, word_offset);
+                        return Err(Error::EncodedWordNotSeparatedError(location));
+                    }
+                    _ => (),
+                }
+                word_len = "=?".len() + charset.len() + "?".len();
             }
             Encoding(encoding) => {
-                curr_encoding = first_char_of(&encoding)?;
+                if !matches!(&encoding[..], b"Q" | b"q" | b"B" | b"b") {
+                    let location = locate(tokens, i, span.start);
+                    let encoding = String::from_utf8_lossy(encoding).to_string();
+                    return Err(Error::UnknownEncodingError(encoding, location));
+                }
+                word_len += encoding.len() + "?".len();
             }
+            EncodedText(encoded_bytes) => {
+                word_len += encoded_bytes.len() + "?=".len();
+                if word_len > MAX_ENCODED_WORD_LEN {
+                    let word_offset = span.end + "?=".len() - word_len;
+                    let location = locate(tokens, i, word_offset + MAX_ENCODED_WORD_LEN);
+                    return Err(Error::EncodedWordTooLongError(word_len, location));
+                }
+                if let Some(j) = encoded_bytes
+                    .iter()
+                    .position(|b| !b.is_ascii_graphic() || *b == b'?')
+                {
+                    let location = locate(tokens, i, span.start + j);
+                    return Err(Error::InvalidEncodedTextError(location));
+                }
+            }
+            ClearText(clear_bytes) => {
+                if let Some(EncodedText(_)) = prev_token {
+                    if !clear_bytes.first().is_some_and(separates_after) {
+                        let location = locate(tokens, i - 1, span.start);
+                        return Err(Error::EncodedWordNotSeparatedError(location));
+                    }
+                }
+            }
+        }
+
+        prev_token = Some(token);
+    }
+
+    Ok(())
+}
+
+pub fn run(tokens: &Tokens, decoder: &Decoder) -> Result<Ast> {
+    if decoder.strict {
+        check_strict(tokens)?;
+    }
+
+    let mut curr_charset: &[u8] = &[];
+    let mut curr_language: Option<&[u8]> = None;
+    let mut curr_encoding: &[u8] = &[];
+    let mut curr_word_start = 0;
+    let mut ast: Ast = vec![];
+
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        use crate::lexer::Token::*;
+
+        match token {
+            Charset(charset) => {
+                (curr_charset, curr_language) = split_language(charset);
+                curr_word_start = span.start - "=?".len();
+            }
+            Encoding(encoding) => curr_encoding = encoding,
             EncodedText(encoded_bytes) => {
                 ast.push(Node::EncodedBytes(EncodedBytes {
-                    charset: curr_charset.clone(),
-                    encoding: curr_encoding,
+                    charset: curr_charset.to_vec(),
+                    language: curr_language.map(<[u8]>::to_vec),
+                    encoding: ast::Encoding::from_label(curr_encoding),
+                    encoding_label: curr_encoding.to_vec(),
                     bytes: encoded_bytes.clone(),
+                    span: curr_word_start..span.end + "?=".len(),
                 }));
             }
-            ClearText(decoded_bytes) => match decoded_bytes[..] {
-                [CR, LF, SPACE] => (),
-                [LF, SPACE] => (),
-                [SPACE] => (),
-                _ => ast.push(Node::ClearBytes(decoded_bytes.clone())),
-            },
+            ClearText(decoded_bytes) => {
+                // Linear whitespace separating two encoded words is
+                // ignored, as stated in RFC 2047 section 6.2.
+                let is_separator = decoded_bytes.iter().all(is_whitespace)
+                    && i > 0
+                    && matches!(tokens[i - 1], (EncodedText(_), _))
+                    && matches!(tokens.get(i + 1), Some((Charset(_), _)));
+
+                if !is_separator {
+                    ast.push(Node::ClearBytes(ClearBytes {
+                        bytes: decoded_bytes.clone(),
+                        span: span.clone(),
+                    }));
+                }
+            }
         }
     }
 
@@ -71,15 +216,74 @@ pub fn run(tokens: &Tokens) -> Result<Ast> {
 
 #[cfg(test)]
 mod tests {
-    use crate::parser;
+    use crate::{
+        lexer,
+        parser::{self, Node},
+        Decoder,
+    };
+
+    #[test]
+    fn language() {
+        let decoder = Decoder::new();
+        let tokens = lexer::run(b"=?utf-8*en-US?q?a?= =?utf-8?q?b?=", &decoder).unwrap();
+        let ast = parser::run(&tokens, &decoder).unwrap();
+
+        match &ast[..] {
+            [Node::EncodedBytes(a), Node::EncodedBytes(b)] => {
+                assert_eq!(a.charset, b"utf-8");
+                assert_eq!(a.language.as_deref(), Some(&b"en-US"[..]));
+                assert_eq!(b.charset, b"utf-8");
+                assert_eq!(b.language, None);
+            }
+            ast => panic!("unexpected ast {:?}", ast),
+        }
+    }
+
+    fn assert_ast(encoded_str: &str, expected_ast: &[&str]) {
+        let decoder = Decoder::new();
+        let tokens = lexer::run(encoded_str.as_bytes(), &decoder).unwrap();
+        let ast = parser::run(&tokens, &decoder)
+            .unwrap()
+            .into_iter()
+            .map(|node| match node {
+                Node::EncodedBytes(node) => format!("={}", String::from_utf8(node.bytes).unwrap()),
+                Node::ClearBytes(node) => String::from_utf8(node.bytes).unwrap(),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(ast, expected_ast);
+    }
 
     #[test]
-    fn first_char_of() {
-        assert_eq!('Q', parser::first_char_of(&vec![]).unwrap());
-        assert_eq!('Q', parser::first_char_of(&"q".as_bytes()).unwrap());
-        assert_eq!('Q', parser::first_char_of(&"Q".as_bytes()).unwrap());
-        assert_eq!('B', parser::first_char_of(&"b".as_bytes()).unwrap());
-        assert_eq!('B', parser::first_char_of(&"B".as_bytes()).unwrap());
-        assert_eq!('B', parser::first_char_of(&"base64".as_bytes()).unwrap());
+    fn whitespace_between_encoded_words() {
+        assert_ast("=?utf-8?q?a?= =?utf-8?q?b?=", &["=a", "=b"]);
+        assert_ast("=?utf-8?q?a?=  \t =?utf-8?q?b?=", &["=a", "=b"]);
+        assert_ast("=?utf-8?q?a?=\r\n\t=?utf-8?q?b?=", &["=a", "=b"]);
+        assert_ast("=?utf-8?q?a?=\r\n \r\n =?utf-8?q?b?=", &["=a", "=b"]);
+    }
+
+    #[test]
+    fn whitespace_next_to_clear_text() {
+        assert_ast(" =?utf-8?q?a?=", &[" ", "=a"]);
+        assert_ast("=?utf-8?q?a?= ", &["=a", " "]);
+        assert_ast("=?utf-8?q?a?=\r\n ", &["=a", "\r\n "]);
+        assert_ast("a =?utf-8?q?b?= c", &["a ", "=b", " c"]);
+        assert_ast(" ", &[" "]);
+    }
+
+    /// Examples of RFC 2047 section 8.
+    #[test]
+    fn rfc_examples() {
+        let assert_decoded = |encoded_str: &str, decoded_str: &str| {
+            assert_eq!(crate::decode(encoded_str.as_bytes()).unwrap(), decoded_str);
+        };
+
+        assert_decoded("(=?ISO-8859-1?Q?a?=)", "(a)");
+        assert_decoded("(=?ISO-8859-1?Q?a?= b)", "(a b)");
+        assert_decoded("(=?ISO-8859-1?Q?a?= =?ISO-8859-1?Q?b?=)", "(ab)");
+        assert_decoded("(=?ISO-8859-1?Q?a?=  =?ISO-8859-1?Q?b?=)", "(ab)");
+        assert_decoded("(=?ISO-8859-1?Q?a?=\r\n    =?ISO-8859-1?Q?b?=)", "(ab)");
+        assert_decoded("(=?ISO-8859-1?Q?a_b?=)", "(a b)");
+        assert_decoded("(=?ISO-8859-1?Q?a?= =?ISO-8859-2?Q?_b?=)", "(a b)");
     }
 }
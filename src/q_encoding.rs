@@ -0,0 +1,135 @@
+/// Repair made to the encoded text of a Q encoded word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum QRepair {
+    /// An escape used lowercase hexadecimal digits, like `=e9`.
+    LowercaseEscape,
+    /// An `=` was not followed by two hexadecimal digits, and was kept
+    /// as is with what follows it.
+    TruncatedEscape,
+    /// A byte other than printable ASCII, like a raw 8-bit byte, was
+    /// kept as is.
+    RawByte,
+    /// Literal whitespace, usually left by a broken folding, was kept as
+    /// is. Line breaks were removed.
+    LiteralWhitespace,
+    /// A `=` followed by a line break, as in quoted-printable bodies,
+    /// was removed.
+    SoftLineBreak,
+}
+
+fn decode_hex_digit(byte: u8) -> Option<(u8, bool)> {
+    match byte {
+        b'0'..=b'9' => Some((byte - b'0', false)),
+        b'A'..=b'F' => Some((byte - b'A' + 10, false)),
+        b'a'..=b'f' => Some((byte - b'a' + 10, true)),
+        _ => None,
+    }
+}
+
+/// Decode Q encoded text, as defined in RFC 2047 section 4.2, returning
+/// the decoded bytes with the repairs made to the encoded text.
+///
+/// Every malformation has a deterministic repair, so decoding never
+/// fails: the encoded text is strictly valid when no repair was made.
+pub fn decode(encoded_bytes: &[u8]) -> (Vec<u8>, Vec<QRepair>) {
+    let mut decoded_bytes = Vec::with_capacity(encoded_bytes.len());
+    let mut repairs = vec![];
+    let mut push_repair = |repair| {
+        if !repairs.contains(&repair) {
+            repairs.push(repair);
+        }
+    };
+    let mut i = 0;
+
+    while i < encoded_bytes.len() {
+        match &encoded_bytes[i..] {
+            [b'=', b'\r', b'\n', ..] => {
+                push_repair(QRepair::SoftLineBreak);
+                i += 3;
+            }
+            [b'=', b'\n', ..] => {
+                push_repair(QRepair::SoftLineBreak);
+                i += 2;
+            }
+            [b'=', hi, lo, ..] => match (decode_hex_digit(*hi), decode_hex_digit(*lo)) {
+                (Some((hi, hi_lowercase)), Some((lo, lo_lowercase))) => {
+                    if hi_lowercase || lo_lowercase {
+                        push_repair(QRepair::LowercaseEscape);
+                    }
+                    decoded_bytes.push(hi << 4 | lo);
+                    i += 3;
+                }
+                _ => {
+                    push_repair(QRepair::TruncatedEscape);
+                    decoded_bytes.push(b'=');
+                    i += 1;
+                }
+            },
+            [b'=', ..] => {
+                push_repair(QRepair::TruncatedEscape);
+                decoded_bytes.push(b'=');
+                i += 1;
+            }
+            [b'_', ..] => {
+                decoded_bytes.push(b' ');
+                i += 1;
+            }
+            [b'\r' | b'\n', ..] => {
+                push_repair(QRepair::LiteralWhitespace);
+                i += 1;
+            }
+            [byte @ (b' ' | b'\t'), ..] => {
+                push_repair(QRepair::LiteralWhitespace);
+                decoded_bytes.push(*byte);
+                i += 1;
+            }
+            [byte, ..] => {
+                if !byte.is_ascii_graphic() {
+                    push_repair(QRepair::RawByte);
+                }
+                decoded_bytes.push(*byte);
+                i += 1;
+            }
+            [] => unreachable!(),
+        }
+    }
+
+    (decoded_bytes, repairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::q_encoding::{self, QRepair::*};
+
+    fn assert_decoded(encoded_bytes: &[u8], decoded_bytes: &[u8], repairs: &[q_encoding::QRepair]) {
+        assert_eq!(
+            q_encoding::decode(encoded_bytes),
+            (decoded_bytes.to_vec(), repairs.to_vec())
+        );
+    }
+
+    #[test]
+    fn decode_valid() {
+        assert_decoded(b"", b"", &[]);
+        assert_decoded(b"str_with_spaces", b"str with spaces", &[]);
+        assert_decoded(b"caf=C3=A9=3F=5F", "café?_".as_bytes(), &[]);
+    }
+
+    #[test]
+    fn decode_repaired() {
+        assert_decoded(b"caf=c3=a9", "café".as_bytes(), &[LowercaseEscape]);
+        assert_decoded(b"a=C", b"a=C", &[TruncatedEscape]);
+        assert_decoded(b"a=", b"a=", &[TruncatedEscape]);
+        assert_decoded(b"1=2", b"1=2", &[TruncatedEscape]);
+        assert_decoded(b"a=XYb", b"a=XYb", &[TruncatedEscape]);
+        assert_decoded(b"caf\xE9", b"caf\xE9", &[RawByte]);
+        assert_decoded(b"a b\tc", b"a b\tc", &[LiteralWhitespace]);
+        assert_decoded(b"a\r\n b", b"a b", &[LiteralWhitespace]);
+        assert_decoded(b"a=\r\nb=\nc", b"abc", &[SoftLineBreak]);
+        assert_decoded(
+            b"caf=e9 =E",
+            b"caf\xE9 =E",
+            &[LowercaseEscape, LiteralWhitespace, TruncatedEscape],
+        );
+    }
+}
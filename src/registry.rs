@@ -0,0 +1,342 @@
+use std::{fmt, sync::Arc};
+
+use charset::Charset;
+
+/// Decoder of a charset, for charsets the registry does not support out
+/// of the box.
+///
+/// ```rust
+/// use rfc2047_decoder::{CharsetDecoder, CharsetRegistry, Decoder};
+///
+/// #[derive(Debug)]
+/// struct Rot13;
+///
+/// impl CharsetDecoder for Rot13 {
+///     fn decode(&self, bytes: &[u8]) -> (String, bool) {
+///         let decoded_str = bytes
+///             .iter()
+///             .map(|b| match b {
+///                 b'a'..=b'z' => ((b - b'a' + 13) % 26 + b'a') as char,
+///                 b'A'..=b'Z' => ((b - b'A' + 13) % 26 + b'A') as char,
+///                 b => *b as char,
+///             })
+///             .collect();
+///         (decoded_str, false)
+///     }
+/// }
+///
+/// let registry = CharsetRegistry::new().decoder("x-rot13", Rot13);
+/// let decoder = Decoder::new().charset_registry(registry);
+///
+/// assert_eq!(decoder.decode(b"=?X_ROT13?Q?fge?=").unwrap(), "str");
+/// ```
+pub trait CharsetDecoder: fmt::Debug + Send + Sync {
+    /// Decode the given bytes, returning the decoded string and whether
+    /// invalid bytes were replaced.
+    fn decode(&self, bytes: &[u8]) -> (String, bool);
+}
+
+/// IBM code page 850, used by DOS and some Windows mail clients in
+/// Western Europe.
+#[derive(Debug)]
+struct Ibm850;
+
+#[rustfmt::skip]
+const IBM850_HIGH_CHARS: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', 'ø', '£', 'Ø', '×', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '®', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', 'Á', 'Â', 'À', '©', '╣', '║', '╗', '╝', '¢', '¥', '┐',
+    '└', '┴', '┬', '├', '─', '┼', 'ã', 'Ã', '╚', '╔', '╩', '╦', '╠', '═', '╬', '¤',
+    'ð', 'Ð', 'Ê', 'Ë', 'È', 'ı', 'Í', 'Î', 'Ï', '┘', '┌', '█', '▄', '¦', 'Ì', '▀',
+    'Ó', 'ß', 'Ô', 'Ò', 'õ', 'Õ', 'µ', 'þ', 'Þ', 'Ú', 'Û', 'Ù', 'ý', 'Ý', '¯', '´',
+    '\u{00AD}', '±', '‗', '¾', '¶', '§', '÷', '¸', '°', '¨', '·', '¹', '³', '²', '■', '\u{00A0}',
+];
+
+impl CharsetDecoder for Ibm850 {
+    fn decode(&self, bytes: &[u8]) -> (String, bool) {
+        let decoded_str = bytes
+            .iter()
+            .map(|b| match b {
+                0x00..=0x7F => *b as char,
+                _ => IBM850_HIGH_CHARS[(b - 0x80) as usize],
+            })
+            .collect();
+        (decoded_str, false)
+    }
+}
+
+/// Aliases of common labels unknown to the WHATWG Encoding Standard.
+const BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("cp-850", "ibm850"),
+    ("cp850", "ibm850"),
+    ("850", "ibm850"),
+    ("cspc850multilingual", "ibm850"),
+    ("cp-866", "ibm866"),
+    ("cp-1250", "windows-1250"),
+    ("cp-1251", "windows-1251"),
+    ("cp-1252", "windows-1252"),
+    ("cp-1253", "windows-1253"),
+    ("cp-1254", "windows-1254"),
+    ("cp-1255", "windows-1255"),
+    ("cp-1256", "windows-1256"),
+    ("cp-1257", "windows-1257"),
+    ("cp-1258", "windows-1258"),
+    ("latin-1", "iso-8859-1"),
+    ("latin-2", "iso-8859-2"),
+    ("utf-8-bom", "utf-8"),
+    ("unicode-2-0-utf-8", "utf-8"),
+];
+
+/// Normalise a charset label: surrounding whitespace and quotes are
+/// removed, and the label is lowercased.
+///
+/// ```rust
+/// assert_eq!(rfc2047_decoder::normalize_charset_label(b" \"UTF-8\" "), "utf-8");
+/// ```
+pub fn normalize_label(label: &[u8]) -> String {
+    let label = String::from_utf8_lossy(label);
+    let label = label.trim();
+    let label = ['"', '\'']
+        .iter()
+        .find_map(|quote| label.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(label);
+
+    label.trim().to_ascii_lowercase()
+}
+
+/// Fold the `_` and `-` separators of a normalised label, so that
+/// `iso_8859_1` and `iso-8859-1` are the same key.
+fn fold_label(label: &str) -> String {
+    label.replace('_', "-")
+}
+
+/// Charset resolved by a [`CharsetRegistry`].
+#[derive(Clone, Debug)]
+pub struct ResolvedCharset(Resolved);
+
+#[derive(Clone, Debug)]
+enum Resolved {
+    Builtin(Charset),
+    Custom(String, Arc<dyn CharsetDecoder>),
+}
+
+impl ResolvedCharset {
+    pub(crate) fn builtin(charset: Charset) -> Self {
+        Self(Resolved::Builtin(charset))
+    }
+
+    /// Canonical name of the charset, as registered by IANA or defined
+    /// by the WHATWG Encoding Standard, like `UTF-8` or `windows-1252`.
+    pub fn name(&self) -> &str {
+        match &self.0 {
+            Resolved::Builtin(charset) => charset.name(),
+            Resolved::Custom(name, _) => name,
+        }
+    }
+
+    /// Decode the given bytes, returning the decoded string and whether
+    /// invalid bytes were replaced.
+    pub fn decode(&self, bytes: &[u8]) -> (String, bool) {
+        match &self.0 {
+            Resolved::Builtin(charset) => {
+                let (decoded_str, _, had_errors) = charset.decode(bytes);
+                (decoded_str.into_owned(), had_errors)
+            }
+            Resolved::Custom(_, decoder) => decoder.decode(bytes),
+        }
+    }
+}
+
+/// Registry resolving the charset labels of encoded words.
+///
+/// ```rust
+/// use rfc2047_decoder::{CharsetRegistry, Decoder};
+///
+/// let registry = CharsetRegistry::new().alias("x-user-defined-latin", "iso-8859-15");
+/// assert_eq!(registry.resolve(b"X_USER_DEFINED_LATIN").unwrap().name(), "ISO-8859-15");
+/// assert_eq!(registry.resolve(b"'cp-850'").unwrap().name(), "IBM850");
+///
+/// let decoder = Decoder::new().charset_registry(registry);
+/// assert_eq!(decoder.decode(b"=?x-user-defined-latin?Q?=A4?=").unwrap(), "€");
+/// ```
+///
+/// Labels are normalised with [`normalize_charset_label`], and `_` and
+/// `-` are interchangeable. Custom decoders come first, then aliases,
+/// then the labels of the WHATWG Encoding Standard and UTF-7. Later
+/// decoders and aliases override earlier ones.
+///
+/// [`normalize_charset_label`]: crate::normalize_charset_label
+#[derive(Clone, Debug)]
+pub struct CharsetRegistry {
+    aliases: Vec<(String, String)>,
+    decoders: Vec<(String, String, Arc<dyn CharsetDecoder>)>,
+}
+
+impl Default for CharsetRegistry {
+    fn default() -> Self {
+        let registry = Self {
+            aliases: vec![],
+            decoders: vec![],
+        };
+
+        BUILTIN_ALIASES.iter().fold(
+            registry.decoder("IBM850", Ibm850),
+            |registry, (alias, label)| registry.alias(alias, label),
+        )
+    }
+}
+
+impl CharsetRegistry {
+    /// Create a registry with the built-in aliases and decoders, like
+    /// `cp-850`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve the given alias as the given label.
+    pub fn alias(mut self, alias: &str, label: &str) -> Self {
+        let alias = fold_label(&normalize_label(alias.as_bytes()));
+        self.aliases.push((alias, label.to_string()));
+        self
+    }
+
+    /// Decode the charset of the given canonical name with the given
+    /// decoder.
+    pub fn decoder<D: CharsetDecoder + 'static>(mut self, name: &str, decoder: D) -> Self {
+        let key = fold_label(&normalize_label(name.as_bytes()));
+        self.decoders
+            .push((key, name.to_string(), Arc::new(decoder)));
+        self
+    }
+
+    fn resolve_decoder(&self, key: &str) -> Option<ResolvedCharset> {
+        self.decoders
+            .iter()
+            .rev()
+            .find(|(k, _, _)| k == key)
+            .map(|(_, name, decoder)| {
+                ResolvedCharset(Resolved::Custom(name.clone(), decoder.clone()))
+            })
+    }
+
+    fn resolve_builtin(label: &str) -> Option<ResolvedCharset> {
+        [
+            label.to_string(),
+            label.replace('_', "-"),
+            label.replace('-', "_"),
+        ]
+        .iter()
+        .find_map(|label| Charset::for_label(label.as_bytes()))
+        .map(ResolvedCharset::builtin)
+    }
+
+    /// Resolve the given charset label, if known.
+    pub fn resolve(&self, label: &[u8]) -> Option<ResolvedCharset> {
+        let label = normalize_label(label);
+        let key = fold_label(&label);
+
+        if let Some(charset) = self.resolve_decoder(&key) {
+            return Some(charset);
+        }
+
+        if let Some((_, target)) = self.aliases.iter().rev().find(|(alias, _)| *alias == key) {
+            let target = normalize_label(target.as_bytes());
+            return self
+                .resolve_decoder(&fold_label(&target))
+                .or_else(|| Self::resolve_builtin(&target));
+        }
+
+        Self::resolve_builtin(&label)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::registry::{normalize_label, CharsetDecoder, CharsetRegistry};
+
+    /// Decoder uppercasing UTF-8, to tell custom decoders apart from the
+    /// built-in ones.
+    #[derive(Debug)]
+    pub(crate) struct Upper;
+
+    impl CharsetDecoder for Upper {
+        fn decode(&self, bytes: &[u8]) -> (String, bool) {
+            (String::from_utf8_lossy(bytes).to_uppercase(), false)
+        }
+    }
+
+    fn name(registry: &CharsetRegistry, label: &[u8]) -> Option<String> {
+        registry
+            .resolve(label)
+            .map(|charset| charset.name().to_string())
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(normalize_label(b"UTF-8"), "utf-8");
+        assert_eq!(normalize_label(b" \"UTF-8\" "), "utf-8");
+        assert_eq!(normalize_label(b"'iso-8859-1'"), "iso-8859-1");
+        assert_eq!(normalize_label(b"\"utf-8"), "\"utf-8");
+    }
+
+    #[test]
+    fn resolve_builtin() {
+        let registry = CharsetRegistry::new();
+
+        assert_eq!(name(&registry, b"utf8").as_deref(), Some("UTF-8"));
+        assert_eq!(name(&registry, b"\"UTF_8\"").as_deref(), Some("UTF-8"));
+        assert_eq!(
+            name(&registry, b"ISO_8859-15").as_deref(),
+            Some("ISO-8859-15")
+        );
+        assert_eq!(name(&registry, b"CP-1252").as_deref(), Some("windows-1252"));
+        assert_eq!(name(&registry, b"cp_850").as_deref(), Some("IBM850"));
+        assert_eq!(
+            name(&registry, b"x-mac-cyrillic").as_deref(),
+            Some("x-mac-cyrillic")
+        );
+        assert_eq!(
+            name(&registry, b"ks_c_5601-1987").as_deref(),
+            Some("EUC-KR")
+        );
+        assert_eq!(
+            name(&registry, b"ISO-8859-8-I").as_deref(),
+            Some("ISO-8859-8-I")
+        );
+        assert_eq!(
+            name(&registry, b"unicode-1-1-utf-8").as_deref(),
+            Some("UTF-8")
+        );
+        assert_eq!(name(&registry, b"x-unknown"), None);
+    }
+
+    #[test]
+    fn decode_ibm850() {
+        let charset = CharsetRegistry::new().resolve(b"cp850").unwrap();
+        assert_eq!(
+            charset.decode(b"caf\x82 \x9C5"),
+            ("café £5".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn resolve_custom() {
+        let registry = CharsetRegistry::new()
+            .alias("x-mac-roman", "macintosh")
+            .alias("cp-1252", "iso-8859-15")
+            .alias("x-shout", "x-upper")
+            .decoder("x-upper", Upper);
+
+        assert_eq!(
+            name(&registry, b"X_MAC_ROMAN").as_deref(),
+            Some("macintosh")
+        );
+        assert_eq!(name(&registry, b"cp-1252").as_deref(), Some("ISO-8859-15"));
+        assert_eq!(name(&registry, b"x-shout").as_deref(), Some("x-upper"));
+        assert_eq!(
+            registry.resolve(b"X-UPPER").unwrap().decode(b"str"),
+            ("STR".to_string(), false)
+        );
+    }
+}
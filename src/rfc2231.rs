@@ -0,0 +1,561 @@
+use std::collections::BTreeMap;
+
+use crate::{evaluator, Decoder, Location, ParamDiagnostic, ParamDiagnosticKind};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    #[error("the percent-encoded octet {0:?} is invalid {1}")]
+    DecodePercentError(String, Location),
+    #[error("the extended value of the parameter {0:?} has no charset and language {1}")]
+    ParseExtendedValueError(String, Location),
+}
+
+/// Values of a parameter, which may be given in several forms.
+#[derive(Debug, Default)]
+struct Param<'a> {
+    value: Option<&'a [u8]>,
+    extended_value: Option<&'a [u8]>,
+    sections: BTreeMap<u32, (bool, &'a [u8])>,
+}
+
+/// Split a parameter name into its lowercased base name, its section
+/// number and whether its value is extended, as in `name*0*`.
+fn parse_name(name: &str) -> (String, Option<u32>, bool) {
+    let (name, extended) = match name.strip_suffix('*') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+
+    match name.rsplit_once('*') {
+        Some((base_name, section))
+            if !section.is_empty() && section.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            match section.parse() {
+                Ok(section) => (base_name.to_lowercase(), Some(section), extended),
+                Err(_) => (name.to_lowercase(), None, extended),
+            }
+        }
+        _ => (name.to_lowercase(), None, extended),
+    }
+}
+
+fn decode_hex_digit(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+pub(crate) fn decode_percent(value: &[u8], start: usize) -> Result<Vec<u8>> {
+    let encoded_bytes = &value[start..];
+    let mut decoded_bytes = Vec::with_capacity(encoded_bytes.len());
+    let mut i = 0;
+
+    while i < encoded_bytes.len() {
+        if encoded_bytes[i] == b'%' {
+            let octet = encoded_bytes.get(i + 1..i + 3);
+            match octet.map(|hex| (decode_hex_digit(hex[0]), decode_hex_digit(hex[1]))) {
+                Some((Some(high), Some(low))) => decoded_bytes.push(high << 4 | low),
+                _ => {
+                    let end = encoded_bytes.len().min(i + 3);
+                    let octet = String::from_utf8_lossy(&encoded_bytes[i..end]).to_string();
+                    let location = Location::new(start + i, 0, value);
+                    return Err(Error::DecodePercentError(octet, location));
+                }
+            }
+            i += 3;
+        } else {
+            decoded_bytes.push(encoded_bytes[i]);
+            i += 1;
+        }
+    }
+
+    Ok(decoded_bytes)
+}
+
+/// Split an extended value into its charset, its language and its
+/// percent-encoded value, as in `utf-8'en'%E2%82%AC`.
+pub(crate) fn split_extended_value(extended_value: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let mut parts = extended_value.splitn(3, |b| *b == b'\'');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(charset), Some(language), Some(value)) => Some((charset, language, value)),
+        _ => None,
+    }
+}
+
+/// Decode a regular value, and its encoded words if asked to, reporting
+/// the problems met.
+pub(crate) fn decode_clear_value(
+    name: &str,
+    value: &[u8],
+    decode_words: bool,
+    decoder: &Decoder,
+    diagnostics: &mut Vec<ParamDiagnostic>,
+) -> String {
+    let mut report = |kind| {
+        diagnostics.push(ParamDiagnostic {
+            name: name.to_string(),
+            kind,
+        })
+    };
+
+    if decode_words && value.windows(2).any(|w| w == b"=?") {
+        match decoder.decode(value) {
+            Ok(decoded_str) => return decoded_str,
+            Err(_) => report(ParamDiagnosticKind::InvalidEncodedWords),
+        }
+    }
+
+    match std::str::from_utf8(value) {
+        Ok(value) => value.to_string(),
+        Err(_) => {
+            report(ParamDiagnosticKind::InvalidUtf8);
+            String::from_utf8_lossy(value).to_string()
+        }
+    }
+}
+
+fn decode_param(
+    name: &str,
+    param: &Param,
+    decoder: &Decoder,
+    diagnostics: &mut Vec<ParamDiagnostic>,
+) -> Result<String> {
+    let decode_words = decoder.lenient_params;
+
+    // Sections must be consecutive and start at 0, others are ignored.
+    let sections = param
+        .sections
+        .iter()
+        .enumerate()
+        .take_while(|(i, (section, _))| *i as u32 == **section)
+        .map(|(_, (_, section))| *section)
+        .collect::<Vec<_>>();
+
+    let sections = if !sections.is_empty() {
+        sections
+    } else if let Some(extended_value) = param.extended_value {
+        vec![(true, extended_value)]
+    } else {
+        let value = param.value.unwrap_or_default();
+        return Ok(decode_clear_value(
+            name,
+            value,
+            decode_words,
+            decoder,
+            diagnostics,
+        ));
+    };
+
+    match (
+        decode_sections(name, &sections, decoder, diagnostics),
+        param.value,
+    ) {
+        // A malformed extended value is skipped in favour of the regular
+        // value, if any.
+        (Err(_), Some(value)) => {
+            diagnostics.push(ParamDiagnostic {
+                name: name.to_string(),
+                kind: ParamDiagnosticKind::InvalidExtendedValue,
+            });
+            Ok(decode_clear_value(
+                name,
+                value,
+                decode_words,
+                decoder,
+                diagnostics,
+            ))
+        }
+        (decoded, _) => decoded,
+    }
+}
+
+/// Decode the continuations of a parameter, or its extended value.
+fn decode_sections(
+    name: &str,
+    sections: &[(bool, &[u8])],
+    decoder: &Decoder,
+    diagnostics: &mut Vec<ParamDiagnostic>,
+) -> Result<String> {
+    let (charset, start) = match sections[0] {
+        (true, value) => match split_extended_value(value) {
+            Some((charset, _, encoded_value)) => (Some(charset), value.len() - encoded_value.len()),
+            None => {
+                let location = Location::new(value.len(), 0, value);
+                return Err(Error::ParseExtendedValueError(name.to_string(), location));
+            }
+        },
+        _ => (None, 0),
+    };
+
+    let mut decoded_bytes = vec![];
+    for (i, &(extended, value)) in sections.iter().enumerate() {
+        if extended {
+            let start = if i == 0 { start } else { 0 };
+            decoded_bytes.extend(decode_percent(value, start)?);
+        } else {
+            decoded_bytes.extend_from_slice(value);
+        }
+    }
+
+    match charset {
+        Some(charset) => Ok(evaluator::decode_with_charset(
+            decoder,
+            charset,
+            &decoded_bytes,
+        )),
+        None => Ok(decode_clear_value(
+            name,
+            &decoded_bytes,
+            decoder.lenient_params,
+            decoder,
+            diagnostics,
+        )),
+    }
+}
+
+/// Decode the given MIME parameters, as defined in RFC 2231.
+///
+/// Parameters are returned in order of appearance, with their names
+/// lowercased and their continuations reassembled. When a parameter is
+/// given in several forms, continuations are preferred over extended
+/// values, which are preferred over regular values, unless they cannot
+/// be decoded.
+pub fn decode_params<N: AsRef<str>, V: AsRef<[u8]>>(
+    params: &[(N, V)],
+    decoder: &Decoder,
+) -> Result<Vec<(String, String)>> {
+    decode_params_reporting(params, decoder, &mut vec![])
+}
+
+/// Decode the given MIME parameters, reporting the problems met.
+pub(crate) fn decode_params_reporting<N: AsRef<str>, V: AsRef<[u8]>>(
+    params: &[(N, V)],
+    decoder: &Decoder,
+    diagnostics: &mut Vec<ParamDiagnostic>,
+) -> Result<Vec<(String, String)>> {
+    let mut grouped_params: Vec<(String, Param)> = vec![];
+
+    for (name, value) in params {
+        let (name, section, extended) = parse_name(name.as_ref());
+        let value = value.as_ref();
+
+        let param = match grouped_params.iter().position(|(n, _)| *n == name) {
+            Some(i) => &mut grouped_params[i].1,
+            None => {
+                grouped_params.push((name, Param::default()));
+                &mut grouped_params.last_mut().unwrap().1
+            }
+        };
+
+        match (section, extended) {
+            (Some(section), _) => {
+                param.sections.insert(section, (extended, value));
+            }
+            (None, true) => param.extended_value = Some(value),
+            (None, false) => param.value = Some(value),
+        }
+    }
+
+    grouped_params
+        .iter()
+        .map(|(name, param)| {
+            let value = decode_param(name, param, decoder, diagnostics)?;
+            Ok((name.clone(), value))
+        })
+        .collect()
+}
+
+const MAX_LINE_LEN: usize = 76;
+
+fn is_attr_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte)
+}
+
+fn encode_percent(c: char, encoded_str: &mut String) {
+    for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+        if is_attr_char(byte) {
+            encoded_str.push(byte as char);
+        } else {
+            encoded_str.push_str(&format!("%{:02X}", byte));
+        }
+    }
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted_str = String::from("\"");
+
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted_str.push('\\');
+        }
+        quoted_str.push(c);
+    }
+
+    quoted_str.push('"');
+    quoted_str
+}
+
+/// Encode the given MIME parameter, as defined in RFC 2231.
+///
+/// ASCII values are kept as regular values when they fit on a line.
+/// Others are percent-encoded as UTF-8, and split into continuations
+/// so that each one fits on its own folded line, preceded by a space
+/// and followed by a `;`.
+pub fn encode_param(name: &str, value: &str) -> Vec<String> {
+    let max_len = MAX_LINE_LEN - " ".len() - ";".len();
+
+    if !value.is_empty() && value.bytes().all(is_attr_char) {
+        let param = format!("{}={}", name, value);
+        if param.len() <= max_len {
+            return vec![param];
+        }
+    }
+
+    if value.bytes().all(|b| b == b' ' || b.is_ascii_graphic()) {
+        let param = format!("{}={}", name, quote(value));
+        if param.len() <= max_len {
+            return vec![param];
+        }
+    }
+
+    let mut param = format!("{}*=utf-8''", name);
+    value.chars().for_each(|c| encode_percent(c, &mut param));
+    if param.len() <= max_len {
+        return vec![param];
+    }
+
+    let mut params = vec![];
+    let mut param = format!("{}*0*=utf-8''", name);
+    let mut prefix_len = param.len();
+
+    for c in value.chars() {
+        let mut encoded_char = String::new();
+        encode_percent(c, &mut encoded_char);
+
+        if param.len() + encoded_char.len() > max_len && param.len() > prefix_len {
+            params.push(param);
+            param = format!("{}*{}*=", name, params.len());
+            prefix_len = param.len();
+        }
+
+        param.push_str(&encoded_char);
+    }
+
+    params.push(param);
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        params::{self, ParamHeader},
+        rfc2231::{self, Error},
+        Decoder, ParamDiagnostic, ParamDiagnosticKind,
+    };
+
+    fn decode(params: &[(&str, &str)]) -> Vec<(String, String)> {
+        rfc2231::decode_params(params, &Decoder::new()).unwrap()
+    }
+
+    fn param(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn regular() {
+        assert_eq!(
+            decode(&[("Name", "a.pdf"), ("charset", "utf-8")]),
+            vec![param("name", "a.pdf"), param("charset", "utf-8")]
+        );
+    }
+
+    #[test]
+    fn extended() {
+        assert_eq!(
+            decode(&[("title*", "us-ascii'en-us'This%20is%20%2A%2A%2Afun%2A%2A%2A")]),
+            vec![param("title", "This is ***fun***")]
+        );
+        assert_eq!(
+            decode(&[("filename*", "iso-8859-1''caf%E9.txt")]),
+            vec![param("filename", "café.txt")]
+        );
+    }
+
+    #[test]
+    fn extended_detected_charset() {
+        let params = [(
+            "filename*",
+            "unknown-8bit''caf%E9%20cr%E8me%20br%FBl%E9e.txt",
+        )];
+
+        assert_eq!(
+            decode(&params),
+            vec![param(
+                "filename",
+                "caf\u{FFFD} cr\u{FFFD}me br\u{FFFD}l\u{FFFD}e.txt"
+            )]
+        );
+        assert_eq!(
+            rfc2231::decode_params(&params, &Decoder::new().detect_charsets(true)).unwrap(),
+            vec![param("filename", "café crème brûlée.txt")]
+        );
+    }
+
+    #[test]
+    fn continuations() {
+        assert_eq!(
+            decode(&[
+                ("filename*1*", "%E6%9C%AC.pdf"),
+                ("filename*0*", "utf-8''%E6%97%A5"),
+            ]),
+            vec![param("filename", "日本.pdf")]
+        );
+        assert_eq!(
+            decode(&[
+                ("title*0*", "us-ascii'en'This%20is%20even%20more%20"),
+                ("title*1*", "%2A%2A%2Afun%2A%2A%2A%20"),
+                ("title*2", "isn't it!"),
+            ]),
+            vec![param("title", "This is even more ***fun*** isn't it!")]
+        );
+        assert_eq!(
+            decode(&[("url*0", "ftp://"), ("url*1", "cs.utk.edu"), ("url*3", "x")]),
+            vec![param("url", "ftp://cs.utk.edu")]
+        );
+    }
+
+    #[test]
+    fn preference() {
+        assert_eq!(
+            decode(&[
+                ("filename", "fallback.txt"),
+                ("filename*", "utf-8''%C3%A9.txt")
+            ]),
+            vec![param("filename", "é.txt")]
+        );
+        assert_eq!(
+            decode(&[
+                ("filename*", "utf-8''a.txt"),
+                ("filename*0*", "utf-8''b.txt")
+            ]),
+            vec![param("filename", "b.txt")]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            rfc2231::decode_params(&[("filename*", "utf-8''%E6%9")], &Decoder::new()),
+            Err(Error::DecodePercentError(octet, location)) if octet == "%9" && location.offset == 10
+        ));
+        assert!(matches!(
+            rfc2231::decode_params(&[("filename*", "%E6%97%A5")], &Decoder::new()),
+            Err(Error::ParseExtendedValueError(name, _)) if name == "filename"
+        ));
+    }
+
+    #[test]
+    fn errors_fallback() {
+        assert_eq!(
+            decode(&[("filename*", "utf-8''%zz"), ("filename", "ok.txt")]),
+            vec![param("filename", "ok.txt")]
+        );
+        assert_eq!(
+            decode(&[("filename", "ok.txt"), ("filename*0*", "%E6%97%A5")]),
+            vec![param("filename", "ok.txt")]
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        let header = params::parse(
+            b"attachment; filename*=utf-8''%zz; filename=\"ok.txt\"; name=\"\xe9\"",
+            &Decoder::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            header.diagnostics,
+            vec![
+                ParamDiagnostic {
+                    name: "filename".to_string(),
+                    kind: ParamDiagnosticKind::InvalidExtendedValue,
+                },
+                ParamDiagnostic {
+                    name: "name".to_string(),
+                    kind: ParamDiagnosticKind::InvalidUtf8,
+                },
+            ]
+        );
+
+        let header = params::parse(
+            b"attachment; filename=\"=?utf-8?x?a?=\"",
+            &Decoder::new().lenient_params(true).strict(true),
+        )
+        .unwrap();
+        assert_eq!(header.param("filename"), Some("=?utf-8?x?a?="));
+        assert_eq!(
+            header.diagnostics,
+            vec![ParamDiagnostic {
+                name: "filename".to_string(),
+                kind: ParamDiagnosticKind::InvalidEncodedWords,
+            }]
+        );
+
+        let header = params::parse(b"text/plain; charset=utf-8", &Decoder::new()).unwrap();
+        assert!(header.diagnostics.is_empty());
+    }
+
+    fn assert_round_trip(value: &str) {
+        let params = rfc2231::encode_param("filename", value);
+        for param in &params {
+            assert!(param.len() <= 74, "param too long: {}", param);
+        }
+        let header_value = format!("attachment;\r\n {}", params.join(";\r\n "));
+        assert_eq!(
+            params::parse(header_value.as_bytes(), &Decoder::new()).unwrap(),
+            ParamHeader {
+                value: "attachment".to_string(),
+                params: vec![param("filename", value)],
+                diagnostics: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn encode() {
+        assert_eq!(rfc2231::encode_param("name", "a.pdf"), vec!["name=a.pdf"]);
+        assert_eq!(rfc2231::encode_param("name", ""), vec!["name=\"\""]);
+        assert_eq!(
+            rfc2231::encode_param("name", "a \"b\".pdf"),
+            vec!["name=\"a \\\"b\\\".pdf\""]
+        );
+        assert_eq!(
+            rfc2231::encode_param("filename", "na\u{EF}ve.txt"),
+            vec!["filename*=utf-8''na%C3%AFve.txt"]
+        );
+    }
+
+    #[test]
+    fn encode_continuations() {
+        let params = rfc2231::encode_param("filename", &"日本".repeat(10));
+        assert_eq!(4, params.len());
+        assert!(params[0].starts_with("filename*0*=utf-8''%E6%97%A5"));
+        assert!(params[1].starts_with("filename*1*=%"));
+        assert!(params[2].starts_with("filename*2*=%"));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        assert_round_trip("a.pdf");
+        assert_round_trip("a \"quoted\" \\ name.pdf");
+        assert_round_trip(&"a long ascii name ".repeat(10));
+        assert_round_trip(&"日本語のファイル名 🦀 ".repeat(10));
+    }
+}
@@ -0,0 +1,101 @@
+use std::ops::Range;
+
+use crate::{registry::ResolvedCharset, Decoder};
+
+/// Charset substituted for the declared charset of encoded words whose
+/// bytes are invalid or implausible in it, see
+/// [`crate::Decoder::charset_fallbacks`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubstitutedCharset {
+    /// Charset label, as written in the encoded words.
+    pub label: String,
+    /// Canonical name of the charset of the fallback chain the words
+    /// were decoded with, like `windows-1252`.
+    pub charset: String,
+    /// Byte span of the encoded words sharing the label in the header.
+    pub span: Range<usize>,
+}
+
+/// Tell whether the given text contains control characters other than
+/// whitespace.
+pub(crate) fn has_controls(text: &str) -> bool {
+    text.chars()
+        .any(|c| c.is_control() && !matches!(c, '\t' | '\r' | '\n'))
+}
+
+/// Tell whether the given bytes, decoded without errors with the given
+/// charset, plausibly are in this charset.
+///
+/// Headers do not contain control characters other than whitespace, and
+/// non-ASCII bytes forming valid UTF-8 are very unlikely to be anything
+/// else than UTF-8.
+pub(crate) fn is_plausible(
+    charset: &ResolvedCharset,
+    decoded_bytes: &[u8],
+    decoded_str: &str,
+) -> bool {
+    let looks_like_utf8 = charset.name() != "UTF-8"
+        && !decoded_bytes.is_ascii()
+        && std::str::from_utf8(decoded_bytes).is_ok();
+
+    !has_controls(decoded_str) && !looks_like_utf8
+}
+
+/// Decode the given bytes with the first charset of the fallback chain
+/// of the decoder they are valid and plausible in.
+pub(crate) fn substitute(
+    decoder: &Decoder,
+    decoded_bytes: &[u8],
+) -> Option<(ResolvedCharset, String)> {
+    decoder
+        .charset_fallbacks
+        .iter()
+        .filter_map(|label| decoder.charsets.resolve(label.as_bytes()))
+        .find_map(|charset| match charset.decode(decoded_bytes) {
+            (decoded_str, false) if is_plausible(&charset, decoded_bytes, &decoded_str) => {
+                Some((charset, decoded_str))
+            }
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{substitution, CharsetRegistry, Decoder};
+
+    fn is_plausible(label: &[u8], decoded_bytes: &[u8]) -> bool {
+        let charset = CharsetRegistry::new().resolve(label).unwrap();
+        let (decoded_str, _) = charset.decode(decoded_bytes);
+        substitution::is_plausible(&charset, decoded_bytes, &decoded_str)
+    }
+
+    #[test]
+    fn plausible() {
+        assert!(is_plausible(b"iso-8859-1", b"caf\xE9"));
+        assert!(is_plausible(b"iso-8859-1", b"cafe\tcreme"));
+        assert!(is_plausible(b"utf-8", "café".as_bytes()));
+        assert!(!is_plausible(b"iso-8859-1", "café".as_bytes()));
+        assert!(!is_plausible(b"iso-8859-2", b"caf\x81"));
+        assert!(!is_plausible(b"utf-8", b"caf\x07"));
+    }
+
+    #[test]
+    fn substitute() {
+        let decoder = Decoder::new().charset_fallbacks(&["x-unknown", "utf-8", "windows-1252"]);
+        let substitute = |decoded_bytes: &[u8]| {
+            let (charset, decoded_str) = substitution::substitute(&decoder, decoded_bytes)?;
+            Some((charset.name().to_string(), decoded_str))
+        };
+
+        assert_eq!(
+            substitute("café".as_bytes()),
+            Some(("UTF-8".to_string(), "café".to_string()))
+        );
+        assert_eq!(
+            substitute(b"caf\xE9"),
+            Some(("windows-1252".to_string(), "café".to_string()))
+        );
+        assert_eq!(substitute(b"caf\x07"), None);
+        assert!(substitution::substitute(&Decoder::new(), b"caf\xE9").is_none());
+    }
+}